@@ -23,14 +23,35 @@ Register-ArgumentCompleter -Native -CommandName 'sd' -ScriptBlock {
         'sd' {
             [CompletionResult]::new('-n', 'n', [CompletionResultType]::ParameterName, 'Limit the number of replacements that can occur per file. 0 indicates unlimited replacements')
             [CompletionResult]::new('--max-replacements', 'max-replacements', [CompletionResultType]::ParameterName, 'Limit the number of replacements that can occur per file. 0 indicates unlimited replacements')
-            [CompletionResult]::new('-f', 'f', [CompletionResultType]::ParameterName, 'Regex flags. May be combined (like `-f mc`).')
-            [CompletionResult]::new('--flags', 'flags', [CompletionResultType]::ParameterName, 'Regex flags. May be combined (like `-f mc`).')
+            [CompletionResult]::new('-f', 'f', [CompletionResultType]::ParameterName, 'Regex flags. May be combined in any order (like `-f mc`), except for
+`c` with `i` and `e` with `m`.')
+            [CompletionResult]::new('--flags', 'flags', [CompletionResultType]::ParameterName, 'Regex flags. May be combined in any order (like `-f mc`), except for
+`c` with `i` and `e` with `m`.')
+            [CompletionResult]::new('--window', 'window', [CompletionResultType]::ParameterName, 'Stream each input through a buffer instead of splitting it into lines, so that patterns can match across line boundaries without loading whole files. Matches longer than SIZE bytes (like `64K` or `1M`) may be missed')
+            [CompletionResult]::new('--record-separator', 'record-separator', [CompletionResultType]::ParameterName, 'Split the input into records at SEP instead of at newlines. Escapes like `\0`, `\r\n` or `\x1e` are supported, and an empty SEP splits it into paragraphs at blank lines. Separators are kept as they are')
+            [CompletionResult]::new('--eol', 'eol', [CompletionResultType]::ParameterName, 'The line endings to write. Either way, the `\r` of `\r\n` line endings is not part of the text matched against')
+            [CompletionResult]::new('--encoding', 'encoding', [CompletionResultType]::ParameterName, 'Decode inputs from ENCODING (like `utf-16le` or `latin1`) before matching, and encode the output back to it. Inputs starting with a UTF-8 or UTF-16 byte order mark are decoded accordingly, and with `auto` all other inputs are matched as raw bytes')
+            [CompletionResult]::new('--output-dir', 'output-dir', [CompletionResultType]::ParameterName, 'Write the output for each file to the same relative path under DIR instead of modifying it in place, creating directories as needed. Absolute paths are taken relative to the root, and paths with `..` are rejected. Binary files that are skipped are copied as they are')
+            [CompletionResult]::new('--write-mode', 'write-mode', [CompletionResultType]::ParameterName, 'How files are modified in place')
+            [CompletionResult]::new('--preserve', 'preserve', [CompletionResultType]::ParameterName, 'File metadata to keep when modifying files in place, on top of their permissions. Files whose contents stay the same are left alone entirely')
+            [CompletionResult]::new('--binary', 'binary', [CompletionResultType]::ParameterName, 'What to do with files that look binary, which is anything with a NUL byte near the start that isn''t UTF-16 text or NUL separated records')
+            [CompletionResult]::new('-j', 'j', [CompletionResultType]::ParameterName, 'The number of files to process in parallel. 0 uses one thread per CPU core. Output to STDOUT is still written one file after another')
+            [CompletionResult]::new('--threads', 'threads', [CompletionResultType]::ParameterName, 'The number of files to process in parallel. 0 uses one thread per CPU core. Output to STDOUT is still written one file after another')
+            [CompletionResult]::new('--error-format', 'error-format', [CompletionResultType]::ParameterName, 'How errors and warnings are printed to STDERR')
+            [CompletionResult]::new('--map', 'map', [CompletionResultType]::ParameterName, 'Replace matches using a lookup table instead of FIND and REPLACE_WITH, which are then omitted. Every key of the table is searched for literally and replaced by its value')
+            [CompletionResult]::new('--map-pattern', 'map-pattern', [CompletionResultType]::ParameterName, 'Look up the text matched by REGEX (or its capture group named `key`) in the `--map` table rather than searching for the keys themselves. Matches that are missing from the table are left untouched')
             [CompletionResult]::new('-p', 'p', [CompletionResultType]::ParameterName, 'Display changes in a human reviewable format (the specifics of the format are likely to change in the future)')
             [CompletionResult]::new('--preview', 'preview', [CompletionResultType]::ParameterName, 'Display changes in a human reviewable format (the specifics of the format are likely to change in the future)')
             [CompletionResult]::new('-F', 'F ', [CompletionResultType]::ParameterName, 'Treat FIND and REPLACE_WITH args as literal strings')
             [CompletionResult]::new('--fixed-strings', 'fixed-strings', [CompletionResultType]::ParameterName, 'Treat FIND and REPLACE_WITH args as literal strings')
             [CompletionResult]::new('-A', 'A ', [CompletionResultType]::ParameterName, 'Process each input as a whole rather than line by line. This allows patterns to match across line boundaries but uses more memory and prevents streaming')
             [CompletionResult]::new('--across', 'across', [CompletionResultType]::ParameterName, 'Process each input as a whole rather than line by line. This allows patterns to match across line boundaries but uses more memory and prevents streaming')
+            [CompletionResult]::new('-z', 'z', [CompletionResultType]::ParameterName, 'Separate records with NUL bytes instead of newlines, like the output of `find -print0`. Short for `--record-separator ''\0''`')
+            [CompletionResult]::new('--null-data', 'null-data', [CompletionResultType]::ParameterName, 'Separate records with NUL bytes instead of newlines, like the output of `find -print0`. Short for `--record-separator ''\0''`')
+            [CompletionResult]::new('--follow-symlinks', 'follow-symlinks', [CompletionResultType]::ParameterName, 'Modify the files that symlinks point to wherever they are. By default symlinks are only followed when they point anywhere under the working directory, or under their own directory if they are outside of it, and others are refused')
+            [CompletionResult]::new('--no-follow', 'no-follow', [CompletionResultType]::ParameterName, 'Refuse to modify files through symlinks')
+            [CompletionResult]::new('--no-warnings', 'no-warnings', [CompletionResultType]::ParameterName, 'Don''t warn about likely mistakes, like replacing with a capture group that FIND doesn''t have')
+            [CompletionResult]::new('--strict-map', 'strict-map', [CompletionResultType]::ParameterName, 'Fail on matches that are missing from the `--map` table instead of leaving them untouched. Files are left unmodified')
             [CompletionResult]::new('-h', 'h', [CompletionResultType]::ParameterName, 'Print help (see more with ''--help'')')
             [CompletionResult]::new('--help', 'help', [CompletionResultType]::ParameterName, 'Print help (see more with ''--help'')')
             [CompletionResult]::new('-V', 'V ', [CompletionResultType]::ParameterName, 'Print version')
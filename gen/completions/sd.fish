@@ -1,7 +1,25 @@
 complete -c sd -s n -l max-replacements -d 'Limit the number of replacements that can occur per file. 0 indicates unlimited replacements' -r
-complete -c sd -s f -l flags -d 'Regex flags. May be combined (like `-f mc`).' -r
+complete -c sd -s f -l flags -d 'Regex flags. May be combined in any order (like `-f mc`), except for
+`c` with `i` and `e` with `m`.' -r
+complete -c sd -l window -d 'Stream each input through a buffer instead of splitting it into lines, so that patterns can match across line boundaries without loading whole files. Matches longer than SIZE bytes (like `64K` or `1M`) may be missed' -r
+complete -c sd -l record-separator -d 'Split the input into records at SEP instead of at newlines. Escapes like `\\0`, `\\r\\n` or `\\x1e` are supported, and an empty SEP splits it into paragraphs at blank lines. Separators are kept as they are' -r
+complete -c sd -l eol -d 'The line endings to write. Either way, the `\\r` of `\\r\\n` line endings is not part of the text matched against' -r -f -a "{preserve	'Keep the ending each line originally had',lf	'End every line with `\\n`',crlf	'End every line with `\\r\\n`'}"
+complete -c sd -l encoding -d 'Decode inputs from ENCODING (like `utf-16le` or `latin1`) before matching, and encode the output back to it. Inputs starting with a UTF-8 or UTF-16 byte order mark are decoded accordingly, and with `auto` all other inputs are matched as raw bytes' -r
+complete -c sd -l output-dir -d 'Write the output for each file to the same relative path under DIR instead of modifying it in place, creating directories as needed. Absolute paths are taken relative to the root, and paths with `..` are rejected. Binary files that are skipped are copied as they are' -r -F
+complete -c sd -l write-mode -d 'How files are modified in place' -r -f -a "{rename	'Write a temp file next to the original, then rename it over the original. Atomic, but hard links to the file are broken',truncate	'Write the new contents into the original file, keeping its inode and hard links. Not atomic: a failure while writing leaves the file partially written',copy-on-write	'Like `rename`, but start from a reflink of the original so that unchanged parts keep sharing storage with it, on filesystems that support it'}"
+complete -c sd -l preserve -d 'File metadata to keep when modifying files in place, on top of their permissions. Files whose contents stay the same are left alone entirely' -r -f -a "{timestamps	'Access and modification times',xattrs	'Extended attributes, other than ACLs',acl	'POSIX access control lists'}"
+complete -c sd -l binary -d 'What to do with files that look binary, which is anything with a NUL byte near the start that isn\'t UTF-16 text or NUL separated records' -r -f -a "{skip	'Leave them alone, with a notice on STDERR',process	'Replace in them like in any other file',error	'Fail without modifying any file'}"
+complete -c sd -s j -l threads -d 'The number of files to process in parallel. 0 uses one thread per CPU core. Output to STDOUT is still written one file after another' -r
+complete -c sd -l error-format -d 'How errors and warnings are printed to STDERR' -r -f -a "{human	'A message for people to read',json	'A JSON object with a stable `code`, and the argument and byte span at fault where there is one'}"
+complete -c sd -l map -d 'Replace matches using a lookup table instead of FIND and REPLACE_WITH, which are then omitted. Every key of the table is searched for literally and replaced by its value' -r -F
+complete -c sd -l map-pattern -d 'Look up the text matched by REGEX (or its capture group named `key`) in the `--map` table rather than searching for the keys themselves. Matches that are missing from the table are left untouched' -r
 complete -c sd -s p -l preview -d 'Display changes in a human reviewable format (the specifics of the format are likely to change in the future)'
 complete -c sd -s F -l fixed-strings -d 'Treat FIND and REPLACE_WITH args as literal strings'
 complete -c sd -s A -l across -d 'Process each input as a whole rather than line by line. This allows patterns to match across line boundaries but uses more memory and prevents streaming'
+complete -c sd -s z -l null-data -d 'Separate records with NUL bytes instead of newlines, like the output of `find -print0`. Short for `--record-separator \'\\0\'`'
+complete -c sd -l follow-symlinks -d 'Modify the files that symlinks point to wherever they are. By default symlinks are only followed when they point anywhere under the working directory, or under their own directory if they are outside of it, and others are refused'
+complete -c sd -l no-follow -d 'Refuse to modify files through symlinks'
+complete -c sd -l no-warnings -d 'Don\'t warn about likely mistakes, like replacing with a capture group that FIND doesn\'t have'
+complete -c sd -l strict-map -d 'Fail on matches that are missing from the `--map` table instead of leaving them untouched. Files are left unmodified'
 complete -c sd -s h -l help -d 'Print help (see more with \'--help\')'
 complete -c sd -s V -l version -d 'Print version'
@@ -1,4 +1,4 @@
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 
 #[derive(Parser, Debug)]
 #[command(
@@ -41,7 +41,8 @@ pub struct Options {
 
     #[arg(short, long, verbatim_doc_comment)]
     #[rustfmt::skip]
-    /** Regex flags. May be combined (like `-f mc`).
+    /** Regex flags. May be combined in any order (like `-f mc`), except for
+`c` with `i` and `e` with `m`.
 
 c - case-sensitive
 
@@ -63,12 +64,137 @@ w - match full words only
     /// prevents streaming.
     pub across: bool,
 
+    #[arg(
+        long,
+        value_name = "SIZE",
+        value_parser = parse_size,
+        conflicts_with = "across"
+    )]
+    /// Stream each input through a buffer instead of splitting it into
+    /// lines, so that patterns can match across line boundaries without
+    /// loading whole files. Matches longer than SIZE bytes (like `64K` or
+    /// `1M`) may be missed.
+    pub window: Option<usize>,
+
+    #[arg(
+        long,
+        value_name = "SEP",
+        allow_hyphen_values = true,
+        conflicts_with_all = ["across", "window"]
+    )]
+    /// Split the input into records at SEP instead of at newlines. Escapes
+    /// like `\0`, `\r\n` or `\x1e` are supported, and an empty SEP splits
+    /// it into paragraphs at blank lines. Separators are kept as they are.
+    pub record_separator: Option<String>,
+
+    #[arg(
+        short = 'z',
+        long = "null-data",
+        conflicts_with_all = ["across", "window", "record_separator"]
+    )]
+    /// Separate records with NUL bytes instead of newlines, like the output
+    /// of `find -print0`. Short for `--record-separator '\0'`.
+    pub null_data: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        value_name = "EOL",
+        default_value_t,
+        conflicts_with_all = ["across", "window", "record_separator", "null_data"]
+    )]
+    /// The line endings to write. Either way, the `\r` of `\r\n` line
+    /// endings is not part of the text matched against.
+    pub eol: Eol,
+
+    #[arg(long, value_name = "ENCODING", default_value = "auto")]
+    /// Decode inputs from ENCODING (like `utf-16le` or `latin1`) before
+    /// matching, and encode the output back to it. Inputs starting with a
+    /// UTF-8 or UTF-16 byte order mark are decoded accordingly, and with
+    /// `auto` all other inputs are matched as raw bytes.
+    pub encoding: String,
+
+    #[arg(
+        long,
+        value_name = "DIR",
+        conflicts_with_all = ["preview", "write_mode", "preserve"]
+    )]
+    /// Write the output for each file to the same relative path under DIR
+    /// instead of modifying it in place, creating directories as needed.
+    /// Absolute paths are taken relative to the root, and paths with `..`
+    /// are rejected. Binary files that are skipped are copied as they are.
+    pub output_dir: Option<std::path::PathBuf>,
+
+    #[arg(long, value_enum, value_name = "MODE", default_value_t)]
+    /// How files are modified in place.
+    pub write_mode: WriteMode,
+
+    #[arg(long, conflicts_with = "no_follow")]
+    /// Modify the files that symlinks point to wherever they are. By
+    /// default symlinks are only followed when they point anywhere under
+    /// the working directory, or under their own directory if they are
+    /// outside of it, and others are refused.
+    pub follow_symlinks: bool,
+
+    #[arg(long)]
+    /// Refuse to modify files through symlinks.
+    pub no_follow: bool,
+
+    #[arg(long, value_enum, value_name = "ATTRS", value_delimiter = ',')]
+    /// File metadata to keep when modifying files in place, on top of
+    /// their permissions. Files whose contents stay the same are left
+    /// alone entirely.
+    pub preserve: Vec<PreserveAttr>,
+
+    #[arg(long, value_enum, value_name = "MODE", default_value_t)]
+    /// What to do with files that look binary, which is anything with a
+    /// NUL byte near the start that isn't UTF-16 text or NUL separated
+    /// records.
+    pub binary: Binary,
+
+    #[arg(short = 'j', long, value_name = "NUM", default_value_t)]
+    /// The number of files to process in parallel. 0 uses one thread per
+    /// CPU core. Output to STDOUT is still written one file after another.
+    pub threads: usize,
+
+    #[arg(long, value_enum, value_name = "FORMAT", default_value_t)]
+    /// How errors and warnings are printed to STDERR.
+    pub error_format: ErrorFormat,
+
+    #[arg(long)]
+    /// Don't warn about likely mistakes, like replacing with a capture
+    /// group that FIND doesn't have.
+    pub no_warnings: bool,
+
+    #[arg(long, value_name = "FILE", conflicts_with = "literal_mode")]
+    /// Replace matches using a lookup table instead of FIND and
+    /// REPLACE_WITH, which are then omitted. Every key of the table is
+    /// searched for literally and replaced by its value.
+    ///
+    /// The format is picked from the extension: a JSON object of strings
+    /// (`.json`), two-column CSV (`.csv`), or `key<TAB>value` lines
+    /// otherwise.
+    pub map: Option<std::path::PathBuf>,
+
+    #[arg(long, value_name = "REGEX", requires = "map")]
+    /// Look up the text matched by REGEX (or its capture group named `key`)
+    /// in the `--map` table rather than searching for the keys themselves.
+    /// Matches that are missing from the table are left untouched.
+    pub map_pattern: Option<String>,
+
+    #[arg(long, requires = "map")]
+    /// Fail on matches that are missing from the `--map` table instead of
+    /// leaving them untouched. Files are left unmodified.
+    pub strict_map: bool,
+
+    #[arg(required_unless_present = "map")]
     /// The regexp or string (if using `-F`) to search for.
-    pub find: String,
+    pub find: Option<String>,
 
+    #[arg(required_unless_present = "map")]
     /// What to replace each match with. Unless in string mode, you may
     /// use captured values like $1, $2, etc.
-    pub replace_with: String,
+    pub replace_with: Option<String>,
 
     /// The path to file(s). This is optional - sd can also read from STDIN.
     ///
@@ -77,6 +203,80 @@ w - match full words only
     pub files: Vec<std::path::PathBuf>,
 }
 
+#[derive(Clone, Copy, Debug, Default, ValueEnum)]
+pub enum Eol {
+    /// Keep the ending each line originally had
+    #[default]
+    Preserve,
+    /// End every line with `\n`
+    Lf,
+    /// End every line with `\r\n`
+    Crlf,
+}
+
+#[derive(Clone, Copy, Debug, Default, ValueEnum)]
+pub enum WriteMode {
+    /// Write a temp file next to the original, then rename it over the
+    /// original. Atomic, but hard links to the file are broken.
+    #[default]
+    Rename,
+    /// Write the new contents into the original file, keeping its inode
+    /// and hard links. Not atomic: a failure while writing leaves the file
+    /// partially written.
+    Truncate,
+    /// Like `rename`, but start from a reflink of the original so that
+    /// unchanged parts keep sharing storage with it, on filesystems that
+    /// support it.
+    CopyOnWrite,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum PreserveAttr {
+    /// Access and modification times
+    Timestamps,
+    /// Extended attributes, other than ACLs
+    Xattrs,
+    /// POSIX access control lists
+    Acl,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum Binary {
+    /// Leave them alone, with a notice on STDERR
+    #[default]
+    Skip,
+    /// Replace in them like in any other file
+    Process,
+    /// Fail without modifying any file
+    Error,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum ErrorFormat {
+    /// A message for people to read
+    #[default]
+    Human,
+    /// A JSON object with a stable `code`, and the argument and byte span
+    /// at fault where there is one
+    Json,
+}
+
+/// Parses a byte count with an optional `K`, `M` or `G` suffix
+fn parse_size(s: &str) -> Result<usize, String> {
+    let (digits, multiplier) = match s.char_indices().last() {
+        Some((i, 'k' | 'K')) => (&s[..i], 1 << 10),
+        Some((i, 'm' | 'M')) => (&s[..i], 1 << 20),
+        Some((i, 'g' | 'G')) => (&s[..i], 1 << 30),
+        _ => (s, 1),
+    };
+    digits
+        .parse::<usize>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .filter(|&n| n > 0)
+        .ok_or_else(|| format!("invalid size `{s}`, expected e.g. 4096 or 64K"))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -88,4 +288,14 @@ mod tests {
         let cmd = Options::command();
         cmd.debug_assert();
     }
+
+    #[test]
+    fn sizes() {
+        assert_eq!(parse_size("4096"), Ok(4096));
+        assert_eq!(parse_size("64K"), Ok(64 * 1024));
+        assert_eq!(parse_size("2m"), Ok(2 * 1024 * 1024));
+        assert!(parse_size("0").is_err());
+        assert!(parse_size("K").is_err());
+        assert!(parse_size("1.5M").is_err());
+    }
 }
@@ -1,41 +1,167 @@
 mod cli;
 
 use clap::Parser;
-use std::{io::stdout, process};
+use std::{io::stdout, path::PathBuf, process};
 
-use sd::{Replacer, Result, Source, process_sources};
+use sd::{
+    Binary, Error, Flags, LineEnding, Preserve, ProcessOptions,
+    RecordSeparator, ReplaceTable, ReplacerBuilder, Result, Source, Symlinks,
+    TextEncoding, WriteMode, process_sources_with,
+};
 
 fn main() {
-    if let Err(e) = try_main() {
-        eprintln!("error: {e}");
+    let options = cli::Options::parse();
+    let error_format = options.error_format;
+    if let Err(e) = try_main(options) {
+        match error_format {
+            cli::ErrorFormat::Human => eprintln!("error: {e}"),
+            cli::ErrorFormat::Json => eprintln!("{}", e.to_json()),
+        }
         process::exit(1);
     }
 }
 
-fn try_main() -> Result<()> {
-    let options = cli::Options::parse();
+fn try_main(options: cli::Options) -> Result<()> {
+    let flags: Flags = options.flags.as_deref().unwrap_or_default().parse()?;
 
-    let replacer = Replacer::new(
-        options.find,
-        options.replace_with,
-        options.literal_mode,
-        options.flags,
-        options.replacements,
-    )?;
+    let map = options.map;
+    let (builder, files) = match &map {
+        Some(map) => {
+            // Without FIND and REPLACE_WITH every positional is a file
+            let files = [options.find, options.replace_with]
+                .into_iter()
+                .flatten()
+                .map(PathBuf::from)
+                .chain(options.files)
+                .collect();
+            let builder = ReplacerBuilder::with_table(
+                ReplaceTable::from_path(map)?,
+                options.map_pattern,
+            );
+            let builder = if options.strict_map {
+                builder.strict_map()
+            } else {
+                builder
+            };
+            (builder, files)
+        }
+        None => {
+            let (Some(find), Some(replace_with)) =
+                (options.find, options.replace_with)
+            else {
+                unreachable!("clap requires FIND and REPLACE_WITH");
+            };
+            let builder = ReplacerBuilder::new(find, replace_with);
+            let builder = if options.literal_mode {
+                builder.literal()
+            } else {
+                builder
+            };
+            (builder, options.files)
+        }
+    };
+    let replacer = builder
+        .flags(flags)
+        .limit(options.replacements)
+        .build()
+        .map_err(|e| match e {
+            Error::InvalidMap(None, reason) => {
+                Error::InvalidMap(map.clone(), reason)
+            }
+            e => with_cli_hint(e),
+        })?;
 
-    let sources = if !options.files.is_empty() {
-        Source::from_paths(options.files)
+    let sources = if !files.is_empty() {
+        Source::from_paths(files)
     } else {
         Ok(Source::from_stdin())
     };
+    let encoding: TextEncoding = options.encoding.parse()?;
     let sources = sources?;
 
     let mut handle = stdout().lock();
-    process_sources(
+    let record_separator = if options.null_data {
+        RecordSeparator::Bytes(vec![b'\0'])
+    } else {
+        options
+            .record_separator
+            .map_or_else(Default::default, |sep| {
+                let Ok(sep) = sep.parse();
+                sep
+            })
+    };
+
+    let preserve = |attr| options.preserve.contains(&attr);
+    let process_options = ProcessOptions {
+        preview: options.preview,
+        across: options.across,
+        window: options.window,
+        record_separator,
+        eol: match options.eol {
+            cli::Eol::Preserve => LineEnding::Preserve,
+            cli::Eol::Lf => LineEnding::Lf,
+            cli::Eol::Crlf => LineEnding::Crlf,
+        },
+        output_dir: options.output_dir,
+        write_mode: match options.write_mode {
+            cli::WriteMode::Rename => WriteMode::Rename,
+            cli::WriteMode::Truncate => WriteMode::Truncate,
+            cli::WriteMode::CopyOnWrite => WriteMode::CopyOnWrite,
+        },
+        symlinks: if options.follow_symlinks {
+            Symlinks::Follow
+        } else if options.no_follow {
+            Symlinks::NoFollow
+        } else {
+            Symlinks::WithinRoot
+        },
+        preserve: Preserve {
+            timestamps: preserve(cli::PreserveAttr::Timestamps),
+            xattrs: preserve(cli::PreserveAttr::Xattrs),
+            acl: preserve(cli::PreserveAttr::Acl),
+        },
+        encoding,
+        binary: match options.binary {
+            cli::Binary::Process => Binary::Process,
+            cli::Binary::Skip => Binary::Skip,
+            cli::Binary::Error => Binary::Error,
+        },
+        threads: options.threads,
+    };
+    if !options.no_warnings {
+        let warnings = replacer.warnings().iter();
+        for warning in warnings.filter(|w| w.applies_to(&process_options)) {
+            match options.error_format {
+                cli::ErrorFormat::Human => eprintln!("warning: {warning}"),
+                cli::ErrorFormat::Json => eprintln!("{}", warning.to_json()),
+            }
+        }
+    }
+
+    let report = process_sources_with(
         &replacer,
         &sources,
-        options.preview,
-        !options.across,
+        &process_options,
         &mut handle,
-    )
+    )?;
+    for entry in report.skipped() {
+        let path = entry.path.as_deref().unwrap_or(entry.name.as_ref());
+        eprintln!("sd: skipping binary file {}", path.display());
+    }
+
+    Ok(())
+}
+
+/// Words hints in terms of the options of the command line
+fn with_cli_hint(error: Error) -> Error {
+    match error {
+        Error::Regex(e) => match e.meant_literally() {
+            Some(c) => Error::Regex(e.with_hint(format!(
+                "To search for a literal `{c}`, escape it like `\\{c}`, or \
+                 use `-F` to search for the whole pattern literally."
+            ))),
+            None => Error::Regex(e),
+        },
+        error => error,
+    }
 }
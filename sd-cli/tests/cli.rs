@@ -80,6 +80,71 @@ mod cli {
         Ok(())
     }
 
+    #[cfg_attr(
+        target_family = "windows",
+        ignore = "Windows symlinks are privileged"
+    )]
+    #[test]
+    fn symlink_policy() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let outside = dir.path().join("outside");
+        let tree = dir.path().join("tree");
+        fs::create_dir(&tree)?;
+        let nearby = tree.join("nearby");
+        fs::write(&outside, "abc")?;
+        fs::write(&nearby, "abc")?;
+        let escaping = tree.join("escaping");
+        let local = tree.join("local");
+        create_soft_link(&outside, &escaping)?;
+        create_soft_link(&nearby, &local)?;
+
+        sd().args(["abc", "def"])
+            .arg(&escaping)
+            .arg(&local)
+            .assert()
+            .failure()
+            .stderr(format!(
+                "error: {} is a symlink to {}, outside of {}\n",
+                escaping.display(),
+                outside.canonicalize()?.display(),
+                tree.canonicalize()?.display()
+            ));
+        assert_file(&outside, "abc");
+        assert_file(&nearby, "abc");
+
+        // Within the working directory, symlinks may point up and across
+        let sub = tree.join("sub");
+        fs::create_dir(&sub)?;
+        create_soft_link(&"../nearby".into(), &sub.join("up"))?;
+        sd().current_dir(&tree)
+            .args(["abc", "xyz", "sub/up"])
+            .assert()
+            .success();
+        assert_file(&nearby, "xyz");
+        sd().current_dir(&sub)
+            .args(["xyz", "abc", "up"])
+            .assert()
+            .failure();
+        assert_file(&nearby, "xyz");
+        fs::write(&nearby, "abc")?;
+
+        sd().args(["--no-follow", "abc", "def"])
+            .arg(&local)
+            .assert()
+            .failure();
+        assert_file(&nearby, "abc");
+
+        sd().args(["--follow-symlinks", "abc", "def"])
+            .arg(&escaping)
+            .arg(&local)
+            .assert()
+            .success();
+        assert_file(&outside, "def");
+        assert_file(&nearby, "def");
+
+        Ok(())
+    }
+
     #[test]
     fn replace_into_stdout() -> Result<()> {
         let mut file = tempfile::NamedTempFile::new()?;
@@ -172,6 +237,114 @@ mod cli {
         "###);
     }
 
+    fn stderr_of(args: &[&str]) -> String {
+        let err = sd().args(args).write_stdin("stdin").unwrap_err();
+        String::from_utf8(err.as_output().unwrap().stderr.clone()).unwrap()
+    }
+
+    #[test]
+    fn invalid_regex_points_at_error() {
+        insta::assert_snapshot!(stderr_of(&["a(b", ""]), @r###"
+        error: invalid regex: unclosed group
+        hint: To search for a literal `(`, escape it like `\(`, or use `-F` to search for the whole pattern literally.
+        a(b
+         ^
+        "###);
+        insta::assert_snapshot!(stderr_of(&["-f", "w", "a)?", ""]), @r###"
+        error: invalid regex: unopened group
+        hint: To search for a literal `)`, escape it like `\)`, or use `-F` to search for the whole pattern literally.
+        a)?
+         ^
+        "###);
+        insta::assert_snapshot!(stderr_of(&["(?<=a)b", "c"]), @r###"
+        error: invalid regex: look-around, including look-ahead and look-behind, is not supported
+        hint: Capture the text before the match instead and put it back, like replacing `(a)b` with `${1}c` rather than `(?<=a)b` with `c`.
+        (?<=a)b
+        ^^^^
+        "###);
+        insta::assert_snapshot!(stderr_of(&["a{2,1}", ""]), @r###"
+        error: invalid regex: invalid repetition count range, the start must be <= the end
+        a{2,1}
+         ^^^^^
+        "###);
+    }
+
+    #[test]
+    fn error_format_json() {
+        let json = |args: &[&str]| {
+            let args = [args, &["--error-format", "json"]].concat();
+            stderr_of(&args)
+        };
+        insta::assert_snapshot!(json(&["a(b", ""]), @r###"
+        {"argument":"find","code":"invalid-regex","hint":"To search for a literal `(`, escape it like `\\(`, or use `-F` to search for the whole pattern literally.","message":"invalid regex: unclosed group","span":{"end":2,"start":1},"value":"a(b"}
+        "###);
+        insta::assert_snapshot!(json(&["(a)", "$1b"]), @r###"
+        {"argument":"replace_with","code":"ambiguous-capture","message":"The numbered capture group `$1` in the replacement text is ambiguous.","span":{"end":3,"start":1},"value":"$1b"}
+        "###);
+        insta::assert_snapshot!(json(&["-f", "ic", "a", "b"]), @r###"
+        {"argument":"flags","code":"conflicting-flags","message":"flags c and i can't be combined"}
+        "###);
+    }
+
+    #[test]
+    fn warns_about_likely_mistakes() {
+        sd().args(["(a)", "$2"])
+            .write_stdin("ab")
+            .assert()
+            .success()
+            .stdout("b")
+            .stderr(
+                "warning: the replacement refers to `$2`, but the pattern \
+                 only has 1 capture group\n",
+            );
+        sd().args(["a\n?", ""])
+            .write_stdin("a\nb")
+            .assert()
+            .success()
+            .stdout("\nb")
+            .stderr(
+                "warning: the pattern looks for a newline in places, which \
+                 lines never contain when replacing line by line (use `-A` to \
+                 replace across lines)\n",
+            );
+        // Only line mode strips newlines
+        sd().args(["-A", "a\n?", ""])
+            .write_stdin("a\nb")
+            .assert()
+            .success()
+            .stdout("b")
+            .stderr("");
+        sd().args(["--no-warnings", "(a)", "$2"])
+            .write_stdin("ab")
+            .assert()
+            .success()
+            .stderr("");
+    }
+
+    #[test]
+    fn fails_on_patterns_that_need_a_newline() {
+        sd().args([r"\n", ","])
+            .write_stdin("a\nb\n")
+            .assert()
+            .failure()
+            .stdout("")
+            .stderr(
+                "error: the pattern only matches across lines, but lines are \
+                 replaced one at a time without their newlines (use `-A` to \
+                 replace across lines)\n",
+            );
+        sd().args(["-A", r"\n", ","])
+            .write_stdin("a\nb\n")
+            .assert()
+            .success()
+            .stdout("a,b,");
+        sd().args(["--record-separator", "", r"a\n+b", "c"])
+            .write_stdin("a\nb\n\nd\n")
+            .assert()
+            .success()
+            .stdout("c\n\nd\n");
+    }
+
     // NOTE: styled terminal output is platform dependent, so convert to a
     // common format, in this case HTML, to check
     #[ignore = "TODO: wait for proper colorization"]
@@ -242,6 +415,69 @@ mod cli {
             .stdout("bar\nfoo\nfoo");
     }
 
+    #[test]
+    fn map_in_place() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let map = dir.path().join("renames.tsv");
+        fs::write(&map, "old_fn\tnew_fn\nOldType\tNewType\n")?;
+        let first = dir.path().join("first");
+        fs::write(&first, "old_fn(OldType)\n")?;
+        let second = dir.path().join("second");
+        fs::write(&second, "let x: OldType = old_fn_2();\n")?;
+
+        sd().arg("--map")
+            .arg(&map)
+            .arg(&first)
+            .arg(&second)
+            .assert()
+            .success();
+        assert_file(&first, "new_fn(NewType)\n");
+        assert_file(&second, "let x: NewType = new_fn_2();\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn map_with_pattern_stdin() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let map = dir.path().join("renames.json");
+        fs::write(&map, r#"{"old": "new"}"#)?;
+
+        sd().arg("--map")
+            .arg(&map)
+            .args(["--map-pattern", r"\b(?P<key>\w+)\("])
+            .write_stdin("old(old) other()")
+            .assert()
+            .success()
+            .stdout("new(old) other()");
+
+        Ok(())
+    }
+
+    #[test]
+    fn strict_map() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let map = dir.path().join("numbers.tsv");
+        fs::write(&map, "one\t1\n")?;
+        let file = dir.path().join("file");
+        fs::write(&file, "one\ntwo\n")?;
+
+        let output = sd()
+            .arg("--map")
+            .arg(&map)
+            .args(["--map-pattern", r"\w+", "--strict-map"])
+            .arg(&file)
+            .output()?;
+        assert!(!output.status.success());
+        assert!(
+            String::from_utf8(output.stderr)?
+                .contains("no entry in the map for the match `two`")
+        );
+        assert_file(&file, "one\ntwo\n");
+
+        Ok(())
+    }
+
     const UNTOUCHED_CONTENTS: &str = "untouched";
 
     fn assert_fails_correctly(
@@ -307,6 +543,281 @@ mod cli {
         Ok(())
     }
 
+    #[test]
+    fn line_by_line_threads_preview_is_ordered() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let paths: Vec<_> = (0..8)
+            .map(|i| {
+                let path = dir.path().join(format!("file{i}"));
+                fs::write(&path, format!("foo {i}\n")).unwrap();
+                path
+            })
+            .collect();
+
+        let expected: String = paths
+            .iter()
+            .enumerate()
+            .map(|(i, path)| {
+                format!("----- FILE {} -----\nbar {i}\n", path.display())
+            })
+            .collect();
+        sd().args(["-j", "4", "-p", "foo", "bar"])
+            .args(&paths)
+            .assert()
+            .success()
+            .stdout(expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn window_matches_across_lines() -> Result<()> {
+        let mut file = tempfile::NamedTempFile::new()?;
+        file.write_all(b"if a {\n}\n  else {\n}\n")?;
+        let path = file.into_temp_path();
+
+        sd().args(["--window", "1K", r"\}\n\s*else", "} else"])
+            .arg(&path)
+            .assert()
+            .success();
+        assert_file(&path, "if a {\n} else {\n}\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn line_by_line_crlf() {
+        sd().args([r"\s+$", ""])
+            .write_stdin("a \r\nb \n")
+            .assert()
+            .success()
+            .stdout("a\r\nb\n");
+
+        sd().args(["--eol", "crlf", "b$", "c"])
+            .write_stdin("a\r\nb\n")
+            .assert()
+            .success()
+            .stdout("a\r\nc\r\n");
+    }
+
+    #[test]
+    fn null_data_records() {
+        sd().args(["-z", r"a\nc", "x"])
+            .write_stdin("a\0a\nc\0a")
+            .assert()
+            .success()
+            .stdout("a\0x\0a");
+    }
+
+    #[test]
+    fn custom_record_separator() {
+        sd().args(["--record-separator", "---\\n", "(?s)\\n(.)", " $1"])
+            .write_stdin("a\nb\n---\nc\nd\n")
+            .assert()
+            .success()
+            .stdout("a b\n---\nc d\n");
+    }
+
+    #[test]
+    fn utf16_in_place() -> Result<()> {
+        let utf16le = |s: &str| -> Vec<u8> {
+            let units = s.encode_utf16().flat_map(u16::to_le_bytes);
+            b"\xFF\xFE".iter().copied().chain(units).collect()
+        };
+        let dir = tempfile::tempdir()?;
+        let file = dir.path().join("strings.rc");
+        fs::write(&file, utf16le("IDS_NAME \"Caf\u{e9}\"\r\n"))?;
+
+        sd().args([r"Caf\w", "Bar"]).arg(&file).assert().success();
+        assert_eq!(fs::read(&file)?, utf16le("IDS_NAME \"Bar\"\r\n"));
+
+        sd().args(["-A", "Bar", "Caf\u{e9}"])
+            .arg(&file)
+            .assert()
+            .success();
+        assert_eq!(fs::read(&file)?, utf16le("IDS_NAME \"Caf\u{e9}\"\r\n"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn unchanged_files_keep_hardlinks() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let file = dir.path().join("file");
+        let link = dir.path().join("link");
+        fs::write(&file, "abc\n")?;
+        fs::hard_link(&file, &link)?;
+
+        for args in [&["b", "b"][..], &["x", "y"], &["-A", "c", "c"]] {
+            sd().args(args).arg(&file).assert().success();
+        }
+
+        fs::write(&link, "still linked")?;
+        assert_file(&file, "still linked");
+
+        Ok(())
+    }
+
+    #[test]
+    fn write_modes() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let file = dir.path().join("file");
+        let link = dir.path().join("link");
+        fs::write(&file, "abc\nabc\n")?;
+        fs::hard_link(&file, &link)?;
+
+        sd().args(["--write-mode", "truncate", "c$", "d"])
+            .arg(&file)
+            .assert()
+            .success();
+        assert_file(&link, "abd\nabd\n");
+
+        sd().args(["-A", "--write-mode", "truncate", "d\n", ""])
+            .arg(&file)
+            .assert()
+            .success();
+        assert_file(&link, "abab");
+
+        sd().args(["--write-mode", "copy-on-write", "b", "x"])
+            .arg(&file)
+            .assert()
+            .success();
+        assert_file(&file, "axax");
+        assert_file(&link, "abab");
+
+        Ok(())
+    }
+
+    #[test]
+    fn output_dir() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let src = dir.path().join("src");
+        fs::create_dir_all(src.join("nested"))?;
+        fs::write(src.join("top.txt"), "name = {{NAME}}\n")?;
+        fs::write(src.join("nested/deep.txt"), "{{NAME}}!")?;
+
+        for args in [&[][..], &["-A"]] {
+            let out = dir.path().join(format!("out{}", args.len()));
+            sd().current_dir(dir.path())
+                .args(args)
+                .arg("--output-dir")
+                .arg(&out)
+                .args(["\\{\\{NAME\\}\\}", "demo"])
+                .args(["src/top.txt", "./src/nested/deep.txt"])
+                .assert()
+                .success();
+            assert_file(&out.join("src/top.txt"), "name = demo\n");
+            assert_file(&out.join("src/nested/deep.txt"), "demo!");
+        }
+        assert_file(&src.join("top.txt"), "name = {{NAME}}\n");
+
+        sd().current_dir(&src)
+            .args(["--output-dir", "out", "a", "b", "../src/top.txt"])
+            .assert()
+            .failure();
+
+        // Skipped binary files are mirrored too
+        fs::write(src.join("logo.png"), b"\x89PNG\0{{NAME}}")?;
+        sd().current_dir(dir.path())
+            .args(["--output-dir", "out", "\\{\\{NAME\\}\\}", "demo"])
+            .args(["src/logo.png", "src/top.txt"])
+            .assert()
+            .success();
+        assert_eq!(
+            fs::read(dir.path().join("out/src/logo.png"))?,
+            b"\x89PNG\0{{NAME}}"
+        );
+        assert_file(&dir.path().join("out/src/top.txt"), "name = demo\n");
+
+        sd().current_dir(dir.path())
+            .args(["--output-dir", "out", "--preserve", "timestamps", "a", "b"])
+            .arg("src/top.txt")
+            .assert()
+            .failure();
+
+        Ok(())
+    }
+
+    #[test]
+    fn preserve_timestamps() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let file = dir.path().join("file");
+        fs::write(&file, "abc")?;
+        let past = std::time::UNIX_EPOCH
+            + std::time::Duration::from_secs(1_000_000_000);
+        fs::File::options()
+            .write(true)
+            .open(&file)?
+            .set_modified(past)?;
+
+        sd().args(["--preserve", "timestamps,xattrs", "abc", "xyz"])
+            .arg(&file)
+            .assert()
+            .success();
+        assert_file(&file, "xyz");
+        assert_eq!(fs::metadata(&file)?.modified()?, past);
+
+        Ok(())
+    }
+
+    #[test]
+    fn binary_files() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let binary = dir.path().join("lib.so");
+        fs::write(&binary, b"\x7fELF\0\0abc")?;
+        let text = dir.path().join("notes.txt");
+        fs::write(&text, "abc\n")?;
+
+        sd().args(["abc", "xyz"])
+            .arg(&binary)
+            .arg(&text)
+            .assert()
+            .success()
+            .stderr(format!("sd: skipping binary file {}\n", binary.display()));
+        assert_file(&binary, "\x7fELF\0\0abc");
+        assert_file(&text, "xyz\n");
+
+        sd().args(["--binary", "error", "xyz", "abc"])
+            .arg(&text)
+            .arg(&binary)
+            .assert()
+            .failure();
+        assert_file(&text, "xyz\n");
+
+        sd().args(["--binary", "process", "abc", "xyz"])
+            .arg(&binary)
+            .assert()
+            .success();
+        assert_file(&binary, "\x7fELF\0\0xyz");
+
+        // NUL separated records aren't binary
+        let records = dir.path().join("records");
+        fs::write(&records, "a\0b\0")?;
+        sd().args(["--binary", "error", "-z", "^a$", "c"])
+            .arg(&records)
+            .assert()
+            .success()
+            .stderr("");
+        assert_file(&records, "c\0b\0");
+
+        Ok(())
+    }
+
+    #[test]
+    fn latin1_stdin() {
+        sd().args(["--encoding", "latin1", r"\w+", "<$0>"])
+            .write_stdin(b"caf\xE9 au lait".as_slice())
+            .assert()
+            .success()
+            .stdout(b"<caf\xE9> <au> <lait>".as_slice());
+
+        sd().args(["--encoding", "klingon", "a", "b"])
+            .write_stdin("a")
+            .assert()
+            .failure()
+            .stderr("error: unknown encoding: klingon\n");
+    }
+
     #[test]
     fn line_by_line_preserves_no_trailing_newline() -> Result<()> {
         sd().args(["abc", "xyz"])
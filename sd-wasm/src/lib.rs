@@ -0,0 +1,77 @@
+//! WebAssembly bindings for the replacement engine of sd, to preview
+//! replacements in a browser with the exact same semantics as the command
+//! line tool
+
+use wasm_bindgen::prelude::*;
+
+/// A find & replace that can be run over any number of inputs
+#[wasm_bindgen]
+pub struct Replacer(sd::Replacer);
+
+#[wasm_bindgen]
+impl Replacer {
+    /// Takes the same arguments as `sd`: `literal` is `-F`, `flags` is `-f`
+    /// and `limit` is `-n`, where 0 means no limit
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        find: String,
+        replace_with: String,
+        literal: bool,
+        flags: Option<String>,
+        limit: usize,
+    ) -> Result<Replacer, JsError> {
+        Self::build(find, replace_with, literal, flags, limit)
+            .map_err(|e| JsError::new(&e.to_string()))
+    }
+
+    /// Replaces all of `input` at once, like `sd --across`
+    ///
+    /// Replacements that leave invalid UTF-8 behind, like a `\xFF` escape,
+    /// come out as U+FFFD.
+    pub fn replace(&self, input: &str) -> String {
+        String::from_utf8_lossy(&self.0.replace(input.as_bytes())).into_owned()
+    }
+
+    /// Like [`Self::replace`], but for any bytes
+    #[wasm_bindgen(js_name = replaceBytes)]
+    pub fn replace_bytes(&self, input: &[u8]) -> Vec<u8> {
+        self.0.replace(input).into_owned()
+    }
+}
+
+impl Replacer {
+    fn build(
+        find: String,
+        replace_with: String,
+        literal: bool,
+        flags: Option<String>,
+        limit: usize,
+    ) -> sd::Result<Self> {
+        sd::Replacer::new(find, replace_with, literal, flags, limit).map(Self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_errors() {
+        let build = |find: &str, replace_with: &str, flags: Option<&str>| {
+            Replacer::build(
+                find.into(),
+                replace_with.into(),
+                false,
+                flags.map(Into::into),
+                0,
+            )
+            .err()
+            .map(|e| e.to_string())
+        };
+
+        assert!(build("(", "", None).unwrap().starts_with("invalid regex"));
+        assert_eq!(build("a", "b", Some("q")).unwrap(), "unknown flag: q");
+        assert!(build("(a)", "$1b", None).is_some());
+        assert!(build("(a)", "${1}b", None).is_none());
+    }
+}
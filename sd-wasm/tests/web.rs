@@ -0,0 +1,58 @@
+//! Runs on a WebAssembly runtime, like with
+//! `CARGO_TARGET_WASM32_UNKNOWN_UNKNOWN_RUNNER=wasm-bindgen-test-runner cargo
+//! test -p sd-wasm --target wasm32-unknown-unknown`
+
+#![cfg(target_arch = "wasm32")]
+
+use sd_wasm::Replacer;
+use wasm_bindgen_test::wasm_bindgen_test;
+
+fn replace(
+    find: &str,
+    replace_with: &str,
+    flags: Option<&str>,
+    input: &str,
+) -> String {
+    Replacer::new(
+        find.into(),
+        replace_with.into(),
+        false,
+        flags.map(Into::into),
+        0,
+    )
+    .unwrap()
+    .replace(input)
+}
+
+#[wasm_bindgen_test]
+fn regex() {
+    assert_eq!(
+        replace(r"(\w+)@(\w+)", "$2 at $1", None, "me@home"),
+        "home at me"
+    );
+    assert_eq!(replace("^a", "b", None, "ab\nab"), "bb\nbb");
+    assert_eq!(replace("^a", "b", Some("e"), "ab\nab"), "bb\nab");
+    assert_eq!(replace("HI", "bye", Some("iw"), "hi this"), "bye this");
+}
+
+#[wasm_bindgen_test]
+fn unescapes() {
+    assert_eq!(replace(",", r"\n", None, "a,b"), "a\nb");
+}
+
+#[wasm_bindgen_test]
+fn literal_and_limit() {
+    let replacer =
+        Replacer::new("a.".into(), "$1".into(), true, None, 1).unwrap();
+    assert_eq!(replacer.replace("a.a.a"), "$1a.a");
+    assert_eq!(replacer.replace_bytes(b"a.\xFF"), b"$1\xFF");
+}
+
+#[wasm_bindgen_test]
+fn invalid_arguments() {
+    assert!(Replacer::new("(".into(), "".into(), false, None, 0).is_err());
+    assert!(
+        Replacer::new("a".into(), "".into(), false, Some("ic".into()), 0)
+            .is_err()
+    );
+}
@@ -0,0 +1,439 @@
+use std::{
+    borrow::Cow,
+    io::{self, BufRead, Read, Write},
+    str::FromStr,
+};
+
+use encoding_rs::{
+    Decoder, DecoderResult, Encoder, EncoderResult, Encoding, UTF_8, UTF_16BE,
+    UTF_16LE,
+};
+
+use crate::{Error, Result};
+
+/// How inputs are decoded before matching
+///
+/// Whatever an input was decoded from, its output is encoded back the same
+/// way, including any byte order mark.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum TextEncoding {
+    /// Decode inputs that start with a UTF-8 or UTF-16 byte order mark, and
+    /// match everything else as raw bytes
+    #[default]
+    Auto,
+    /// Decode inputs from this encoding, unless they start with a byte order
+    /// mark saying otherwise
+    Fixed(&'static Encoding),
+}
+
+impl FromStr for TextEncoding {
+    type Err = Error;
+
+    /// Parses `auto` or any WHATWG encoding label, like `utf-16le` or
+    /// `latin1`
+    fn from_str(s: &str) -> Result<Self> {
+        if s.eq_ignore_ascii_case("auto") {
+            return Ok(Self::Auto);
+        }
+        Encoding::for_label(s.as_bytes())
+            .map(Self::Fixed)
+            .ok_or_else(|| Error::UnknownEncoding(s.to_owned()))
+    }
+}
+
+impl TextEncoding {
+    /// Picks how to transcode an input starting with `head`, or `None` if it
+    /// gets matched as is
+    pub(crate) fn detect(self, head: &[u8]) -> Option<Transcoding> {
+        if let Some((encoding, bom_len)) = Encoding::for_bom(head) {
+            return Some(Transcoding { encoding, bom_len });
+        }
+        match self {
+            Self::Fixed(encoding) if encoding != UTF_8 => Some(Transcoding {
+                encoding,
+                bom_len: 0,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// The encoding an input gets decoded from, and its output encoded back to
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Transcoding {
+    encoding: &'static Encoding,
+    /// Length of the byte order mark the input starts with, if any
+    bom_len: usize,
+}
+
+impl Transcoding {
+    /// Decodes all of `input` to UTF-8, leaving out the byte order mark
+    pub(crate) fn decode<'a>(&self, input: &'a [u8]) -> Result<Cow<'a, [u8]>> {
+        let input = &input[self.bom_len..];
+        if self.encoding == UTF_8 {
+            return Ok(Cow::Borrowed(input));
+        }
+
+        let decoded = self
+            .encoding
+            .decode_without_bom_handling_and_without_replacement(input)
+            .ok_or_else(|| self.malformed())?;
+        Ok(match decoded {
+            Cow::Borrowed(s) => Cow::Borrowed(s.as_bytes()),
+            Cow::Owned(s) => Cow::Owned(s.into_bytes()),
+        })
+    }
+
+    /// Encodes UTF-8 `text` back to the input's encoding, starting with the
+    /// same byte order mark
+    pub(crate) fn encode(&self, text: &[u8]) -> Result<Vec<u8>> {
+        let mut writer = EncodeWriter::new(Vec::new(), *self)?;
+        writer.write_all(text)?;
+        Ok(writer.finish()?)
+    }
+
+    /// Skips the byte order mark of `reader` and decodes the rest as it is
+    /// read
+    pub(crate) fn decode_reader<'a>(
+        &self,
+        mut reader: Box<dyn BufRead + 'a>,
+    ) -> io::Result<Box<dyn BufRead + 'a>> {
+        let bom_len = self.bom_len as u64;
+        io::copy(&mut reader.by_ref().take(bom_len), &mut io::sink())?;
+        if self.encoding == UTF_8 {
+            return Ok(reader);
+        }
+
+        Ok(Box::new(DecodeReader {
+            inner: reader,
+            transcoding: *self,
+            decoder: self.encoding.new_decoder_without_bom_handling(),
+            buf: Vec::new(),
+            pos: 0,
+            done: false,
+        }))
+    }
+
+    /// Starts decoding input that gets pushed in chunks, instead of being
+    /// pulled from a reader like with [`Self::decode_reader`]
+    #[cfg(feature = "async")]
+    pub(crate) fn chunk_decoder(&self) -> ChunkDecoder {
+        ChunkDecoder {
+            transcoding: *self,
+            decoder: self.encoding.new_decoder_without_bom_handling(),
+            bom_left: self.bom_len,
+        }
+    }
+
+    pub(crate) fn is_utf16(&self) -> bool {
+        self.encoding == UTF_16LE || self.encoding == UTF_16BE
+    }
+
+    fn malformed(&self) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("input is not valid {}", self.encoding.name()),
+        )
+    }
+
+    fn bom(&self) -> &'static [u8] {
+        match self.bom_len {
+            0 => b"",
+            _ if self.encoding == UTF_16LE => b"\xFF\xFE",
+            _ if self.encoding == UTF_16BE => b"\xFE\xFF",
+            _ => b"\xEF\xBB\xBF",
+        }
+    }
+}
+
+/// Decodes a reader to UTF-8, refusing malformed input rather than writing
+/// back a lossy copy of it
+struct DecodeReader<R> {
+    inner: R,
+    transcoding: Transcoding,
+    decoder: Decoder,
+    buf: Vec<u8>,
+    pos: usize,
+    done: bool,
+}
+
+impl<R: BufRead> Read for DecodeReader<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let n = available.len().min(out.len());
+        out[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for DecodeReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        while self.pos == self.buf.len() && !self.done {
+            let input = self.inner.fill_buf()?;
+            let last = input.is_empty();
+            let capacity = self
+                .decoder
+                .max_utf8_buffer_length_without_replacement(input.len())
+                .ok_or_else(|| io::Error::other("input too large"))?;
+            self.buf.resize(capacity, 0);
+            self.pos = 0;
+
+            let (result, read, written) = self
+                .decoder
+                .decode_to_utf8_without_replacement(input, &mut self.buf, last);
+            self.buf.truncate(written);
+            self.inner.consume(read);
+            match result {
+                DecoderResult::Malformed(..) => {
+                    return Err(self.transcoding.malformed());
+                }
+                DecoderResult::InputEmpty => self.done = last,
+                DecoderResult::OutputFull => {}
+            }
+        }
+
+        Ok(&self.buf[self.pos..])
+    }
+
+    fn consume(&mut self, n: usize) {
+        self.pos += n;
+    }
+}
+
+/// Decodes input to UTF-8 a chunk at a time, refusing malformed input like
+/// [`DecodeReader`] does
+#[cfg(feature = "async")]
+pub(crate) struct ChunkDecoder {
+    transcoding: Transcoding,
+    decoder: Decoder,
+    /// How much of the BOM is still to be skipped
+    bom_left: usize,
+}
+
+#[cfg(feature = "async")]
+impl ChunkDecoder {
+    /// Appends the decoded `input` to `out`, where `last` marks the end of
+    /// the input
+    pub(crate) fn decode(
+        &mut self,
+        input: &[u8],
+        last: bool,
+        out: &mut Vec<u8>,
+    ) -> io::Result<()> {
+        let skip = self.bom_left.min(input.len());
+        let input = &input[skip..];
+        self.bom_left -= skip;
+        if self.transcoding.encoding == UTF_8 {
+            out.extend_from_slice(input);
+            return Ok(());
+        }
+
+        let start = out.len();
+        let capacity = self
+            .decoder
+            .max_utf8_buffer_length_without_replacement(input.len())
+            .ok_or_else(|| io::Error::other("input too large"))?;
+        out.resize(start + capacity, 0);
+        let (result, _, written) = self
+            .decoder
+            .decode_to_utf8_without_replacement(input, &mut out[start..], last);
+        out.truncate(start + written);
+        match result {
+            DecoderResult::Malformed(..) => Err(self.transcoding.malformed()),
+            DecoderResult::InputEmpty => Ok(()),
+            DecoderResult::OutputFull => {
+                unreachable!("the output has room for all of the input")
+            }
+        }
+    }
+}
+
+/// Encodes the UTF-8 written to it into the encoding of a [`Transcoding`]
+///
+/// [`EncodeWriter::finish`] must be called once everything is written.
+pub(crate) struct EncodeWriter<W: Write> {
+    inner: W,
+    encoding: &'static Encoding,
+    /// Encodes into anything but UTF-8 and UTF-16
+    encoder: Encoder,
+    /// The start of a character that was cut off at the end of a write
+    pending: Vec<u8>,
+    out: Vec<u8>,
+}
+
+impl<W: Write> EncodeWriter<W> {
+    pub(crate) fn new(
+        mut inner: W,
+        transcoding: Transcoding,
+    ) -> io::Result<Self> {
+        inner.write_all(transcoding.bom())?;
+        Ok(Self {
+            inner,
+            encoding: transcoding.encoding,
+            encoder: transcoding.encoding.new_encoder(),
+            pending: Vec::new(),
+            out: Vec::new(),
+        })
+    }
+
+    /// The writer the encoded output goes to
+    #[cfg(feature = "async")]
+    pub(crate) fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    /// Writes out anything still held back, returning the inner writer
+    pub(crate) fn finish(mut self) -> io::Result<W> {
+        if !self.pending.is_empty() {
+            return Err(self.unencodable("output is not valid UTF-8"));
+        }
+        self.encode("", true)?;
+        Ok(self.inner)
+    }
+
+    fn encode(&mut self, mut text: &str, last: bool) -> io::Result<()> {
+        if self.encoding == UTF_8 {
+            return self.inner.write_all(text.as_bytes());
+        }
+
+        self.out.clear();
+        // `encoding_rs` can only decode UTF-16, so that's encoded by hand
+        if self.encoding == UTF_16LE {
+            self.out
+                .extend(text.encode_utf16().flat_map(u16::to_le_bytes));
+        } else if self.encoding == UTF_16BE {
+            self.out
+                .extend(text.encode_utf16().flat_map(u16::to_be_bytes));
+        } else {
+            loop {
+                let needed = self
+                    .encoder
+                    .max_buffer_length_from_utf8_without_replacement(text.len())
+                    .ok_or_else(|| io::Error::other("output too large"))?;
+                self.out.reserve(needed);
+                let (result, read) =
+                    self.encoder.encode_from_utf8_to_vec_without_replacement(
+                        text,
+                        &mut self.out,
+                        last,
+                    );
+                text = &text[read..];
+                match result {
+                    EncoderResult::InputEmpty => break,
+                    EncoderResult::OutputFull => {}
+                    EncoderResult::Unmappable(c) => {
+                        return Err(self.unencodable(&format!("{c:?}")));
+                    }
+                }
+            }
+        }
+
+        self.inner.write_all(&self.out)
+    }
+
+    fn unencodable(&self, what: &str) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{what} can't be encoded as {}", self.encoding.name()),
+        )
+    }
+}
+
+impl<W: Write> Write for EncodeWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.encoding == UTF_8 {
+            // Matched as raw bytes, so written back as is
+            return self.inner.write(buf);
+        }
+
+        let mut pending = std::mem::take(&mut self.pending);
+        pending.extend_from_slice(buf);
+
+        let valid = match std::str::from_utf8(&pending) {
+            Ok(text) => text.len(),
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(_) => {
+                return Err(self.unencodable("output is not valid UTF-8"));
+            }
+        };
+        // Only a cut off character is left after `valid`
+        let text = std::str::from_utf8(&pending[..valid]).unwrap();
+        self.encode(text, false)?;
+        self.pending = pending.split_off(valid);
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::BufReader;
+
+    use super::*;
+
+    fn utf16le(s: &str) -> Vec<u8> {
+        s.encode_utf16().flat_map(u16::to_le_bytes).collect()
+    }
+
+    #[test]
+    fn detect() {
+        let auto = TextEncoding::Auto;
+        let latin1: TextEncoding = "latin1".parse().unwrap();
+
+        assert!(auto.detect(b"plain").is_none());
+        assert!(
+            "utf-8"
+                .parse::<TextEncoding>()
+                .unwrap()
+                .detect(b"x")
+                .is_none()
+        );
+        assert_eq!(auto.detect(b"\xFF\xFEa\0").unwrap().encoding, UTF_16LE);
+        assert_eq!(auto.detect(b"\xEF\xBB\xBFa").unwrap().bom_len, 3);
+        // A byte order mark wins over the given encoding
+        assert_eq!(latin1.detect(b"\xFE\xFF\0a").unwrap().encoding, UTF_16BE);
+        assert_eq!(latin1.detect(b"caf\xE9").unwrap().bom_len, 0);
+
+        assert!("klingon".parse::<TextEncoding>().is_err());
+    }
+
+    #[test]
+    fn round_trip() {
+        let input =
+            [b"\xFF\xFE".as_slice(), &utf16le("h\u{e9}llo \u{1F600}")].concat();
+        let transcoding = TextEncoding::Auto.detect(&input).unwrap();
+
+        let decoded = transcoding.decode(&input).unwrap();
+        assert_eq!(decoded, "h\u{e9}llo \u{1F600}".as_bytes());
+        assert_eq!(transcoding.encode(&decoded).unwrap(), input);
+
+        // Byte by byte, so characters get split across reads and writes
+        let reader = transcoding
+            .decode_reader(Box::new(BufReader::with_capacity(
+                1,
+                input.as_slice(),
+            )))
+            .unwrap();
+        let mut writer = EncodeWriter::new(Vec::new(), transcoding).unwrap();
+        for byte in reader.bytes() {
+            writer.write_all(&[byte.unwrap()]).unwrap();
+        }
+        assert_eq!(writer.finish().unwrap(), input);
+    }
+
+    #[test]
+    fn rejects_malformed_and_unmappable() {
+        let transcoding = TextEncoding::Fixed(UTF_16LE).detect(b"").unwrap();
+        assert!(transcoding.decode(b"\x00\xD8").is_err());
+
+        let transcoding = TextEncoding::Fixed(encoding_rs::SHIFT_JIS)
+            .detect(b"")
+            .unwrap();
+        assert!(transcoding.encode("\u{e9}".as_bytes()).is_err());
+    }
+}
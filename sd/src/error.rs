@@ -1,21 +1,59 @@
-use std::{fmt, path::PathBuf};
+use std::{fmt, ops::Range, path::PathBuf};
 
-use crate::replacer::InvalidReplaceCapture;
+use serde_json::{Value, json};
+
+use crate::replacer::{InvalidRegex, InvalidReplaceCapture};
 
 #[derive(thiserror::Error)]
 pub enum Error {
-    #[error("invalid regex {0}")]
-    Regex(#[from] regex::Error),
+    #[error("{0}")]
+    Regex(#[from] InvalidRegex),
     #[error(transparent)]
     File(#[from] std::io::Error),
+    #[cfg(feature = "io")]
     #[error("failed to move file: {0}")]
     TempfilePersist(#[from] tempfile::PersistError),
+    #[error(
+        "failed writing in place, the file may be left partially written: {0}"
+    )]
+    PartialWrite(std::io::Error),
+    #[error("{0} is a symlink, which isn't followed")]
+    Symlink(PathBuf),
+    #[error("{0} is a symlink to {1}, outside of {2}")]
+    SymlinkOutsideRoot(PathBuf, PathBuf, PathBuf),
     #[error("invalid path: {0}")]
     InvalidPath(PathBuf),
+    #[error("{0} looks like a binary file")]
+    BinaryFile(PathBuf),
+    #[error("invalid map{}: {1}", in_file(.0))]
+    InvalidMap(Option<PathBuf>, String),
+    #[error("no entry in the map for the match `{0}`")]
+    UnmappedMatch(String),
+    #[error("unknown flag: {0}")]
+    UnknownFlag(char),
+    #[error("flags {0} and {1} can't be combined")]
+    ConflictingFlags(char, char),
+    #[error("unknown encoding: {0}")]
+    UnknownEncoding(String),
     #[error("{0}")]
     InvalidReplaceCapture(#[from] InvalidReplaceCapture),
+    #[error(
+        "the pattern only matches across lines, but lines are replaced one \
+         at a time without their newlines (use `-A` to replace across lines)"
+    )]
+    NeedsNewline,
     #[error("{0}")]
     FailedJobs(FailedJobs),
+    #[cfg(feature = "io")]
+    #[error("failed to start worker threads: {0}")]
+    ThreadPool(#[from] rayon::ThreadPoolBuildError),
+}
+
+/// Names the file in an error message, if there is one
+fn in_file(path: &Option<PathBuf>) -> String {
+    path.as_ref()
+        .map(|path| format!(" {}", path.display()))
+        .unwrap_or_default()
 }
 
 // pretty-print the error
@@ -27,6 +65,137 @@ impl fmt::Debug for Error {
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
 
+/// An argument of `sd` that an [`Error`] is about
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Argument {
+    Find,
+    ReplaceWith,
+    Flags,
+    Map,
+    Encoding,
+}
+
+impl Argument {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Find => "find",
+            Self::ReplaceWith => "replace_with",
+            Self::Flags => "flags",
+            Self::Map => "map",
+            Self::Encoding => "encoding",
+        }
+    }
+}
+
+impl Error {
+    /// Identifies the kind of error, and unlike the message stays the same
+    /// across versions
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Regex(_) => "invalid-regex",
+            Self::File(_) => "io",
+            #[cfg(feature = "io")]
+            Self::TempfilePersist(_) => "persist-failed",
+            Self::PartialWrite(_) => "partial-write",
+            Self::Symlink(_) => "symlink",
+            Self::SymlinkOutsideRoot(..) => "symlink-outside-root",
+            Self::InvalidPath(_) => "invalid-path",
+            Self::BinaryFile(_) => "binary-file",
+            Self::InvalidMap(..) => "invalid-map",
+            Self::UnmappedMatch(_) => "unmapped-match",
+            Self::UnknownFlag(_) => "unknown-flag",
+            Self::ConflictingFlags(..) => "conflicting-flags",
+            Self::UnknownEncoding(_) => "unknown-encoding",
+            Self::InvalidReplaceCapture(_) => "ambiguous-capture",
+            Self::NeedsNewline => "needs-newline",
+            Self::FailedJobs(_) => "failed-jobs",
+            #[cfg(feature = "io")]
+            Self::ThreadPool(_) => "thread-pool",
+        }
+    }
+
+    /// The argument that's at fault, if it's any single one of them
+    pub fn argument(&self) -> Option<Argument> {
+        match self {
+            Self::Regex(_) | Self::NeedsNewline => Some(Argument::Find),
+            Self::InvalidReplaceCapture(_) => Some(Argument::ReplaceWith),
+            Self::UnknownFlag(_) | Self::ConflictingFlags(..) => {
+                Some(Argument::Flags)
+            }
+            Self::InvalidMap(..) | Self::UnmappedMatch(_) => {
+                Some(Argument::Map)
+            }
+            Self::UnknownEncoding(_) => Some(Argument::Encoding),
+            _ => None,
+        }
+    }
+
+    /// Byte offsets of the part of [`Self::argument`] that's at fault
+    pub fn span(&self) -> Option<Range<usize>> {
+        match self {
+            Self::Regex(e) => e.span(),
+            Self::InvalidReplaceCapture(e) => Some(e.span()),
+            _ => None,
+        }
+    }
+
+    /// Renders the error for tools to read, with its code, argument, span
+    /// and any paths it's about next to the message
+    pub fn to_json(&self) -> Value {
+        let message = self.to_string();
+        let mut json = json!({
+            "code": self.code(),
+            // Anything after the first line points out the span
+            "message": message.lines().next().unwrap_or_default(),
+        });
+        if let Some(argument) = self.argument() {
+            json["argument"] = json!(argument.name());
+        }
+        match self {
+            Self::Regex(e) => {
+                json["value"] = json!(e.pattern());
+                if let Some(hint) = e.hint() {
+                    json["hint"] = json!(hint);
+                }
+            }
+            Self::InvalidReplaceCapture(e) => {
+                json["value"] = json!(e.replace_with())
+            }
+            Self::UnmappedMatch(key) => json["value"] = json!(key),
+            _ => {}
+        }
+        if let Some(span) = self.span() {
+            json["span"] = json!({ "start": span.start, "end": span.end });
+        }
+        match self {
+            Self::Symlink(path)
+            | Self::InvalidPath(path)
+            | Self::BinaryFile(path)
+            | Self::InvalidMap(Some(path), _) => {
+                json["path"] = json!(path.display().to_string())
+            }
+            Self::SymlinkOutsideRoot(path, target, root) => {
+                json["path"] = json!(path.display().to_string());
+                json["target"] = json!(target.display().to_string());
+                json["root"] = json!(root.display().to_string());
+            }
+            Self::FailedJobs(FailedJobs(jobs)) => {
+                json["errors"] = jobs
+                    .iter()
+                    .map(|(path, error)| {
+                        let mut json = error.to_json();
+                        json["path"] = json!(path.display().to_string());
+                        json
+                    })
+                    .collect();
+            }
+            _ => {}
+        }
+
+        json
+    }
+}
+
 pub struct FailedJobs(pub Vec<(PathBuf, Error)>);
 
 impl fmt::Display for FailedJobs {
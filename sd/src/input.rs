@@ -1,10 +1,15 @@
 use std::{
-    fs::File,
-    io::{BufRead, BufReader, Read, stdin},
-    path::PathBuf,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, Read, stdin},
+    ops::Deref,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
 };
 
-use crate::error::{Error, Result};
+use crate::{
+    ProcessOptions,
+    error::{Error, Result},
+};
 
 #[derive(Debug, PartialEq)]
 pub enum Source {
@@ -38,6 +43,27 @@ impl Source {
     }
 }
 
+/// The full contents of an [`Input`]
+pub enum Contents {
+    Read(Vec<u8>),
+    Shared(Arc<[u8]>),
+    #[cfg(all(feature = "mmap", unix))]
+    Mapped(memmap2::Mmap),
+}
+
+impl Deref for Contents {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Self::Read(buf) => buf,
+            Self::Shared(buf) => buf,
+            #[cfg(all(feature = "mmap", unix))]
+            Self::Mapped(map) => map,
+        }
+    }
+}
+
 pub fn open_source(source: &Source) -> Result<Box<dyn BufRead + '_>> {
     match source {
         Source::File(path) => {
@@ -51,9 +77,225 @@ pub fn open_source(source: &Source) -> Result<Box<dyn BufRead + '_>> {
     }
 }
 
+/// Guesses whether `source` is a binary file rather than text, from its first
+/// few kilobytes
+///
+/// Like git and grep, anything with a NUL byte in it counts as binary, unless
+/// the [`ProcessOptions::encoding`] has it decoded as UTF-16, or NUL bytes
+/// separate its records. STDIN is never considered binary.
+pub fn is_binary(source: &Source, options: &ProcessOptions) -> Result<bool> {
+    match source {
+        Source::File(path) => is_binary_file(path, options),
+        Source::Stdin => Ok(false),
+    }
+}
+
+pub(crate) fn is_binary_file(
+    path: &Path,
+    options: &ProcessOptions,
+) -> Result<bool> {
+    const HEAD_LEN: u64 = 8192;
+
+    if options.record_separator.has_nul() {
+        return Ok(false);
+    }
+    let mut head = Vec::new();
+    File::open(path)?.take(HEAD_LEN).read_to_end(&mut head)?;
+
+    Ok(memchr::memchr(0, &head).is_some()
+        && !options.encoding.detect(&head).is_some_and(|t| t.is_utf16()))
+}
+
 pub fn read_source(source: &Source) -> Result<Vec<u8>> {
     let mut handle = open_source(source)?;
     let mut buf = Vec::new();
     handle.read_to_end(&mut buf)?;
     Ok(buf)
 }
+
+/// Like [`read_source`], but memory-maps regular files when the `mmap`
+/// feature is enabled
+///
+/// Anything that can't be mapped (stdin, pipes, empty files, or a failed
+/// `mmap` call) falls back to being read into memory.
+pub fn map_source(source: &Source) -> Result<Contents> {
+    #[cfg(all(feature = "mmap", unix))]
+    if let Source::File(path) = source {
+        let file = File::open(path)?;
+        let metadata = file.metadata()?;
+        if metadata.is_file() && metadata.len() > 0 {
+            // SAFETY: the mapping is only ever read from. Another process
+            // truncating the file while we read it is the usual caveat of
+            // `mmap`, and the same tradeoff is made by tools like ripgrep
+            if let Ok(map) = unsafe { memmap2::Mmap::map(&file) } {
+                return Ok(Contents::Mapped(map));
+            }
+        }
+    }
+
+    read_source(source).map(Contents::Read)
+}
+
+/// Something to replace the contents of, like a [`Source`], a [`Buffer`] or a
+/// [`Reader`]
+pub trait Input: Sync {
+    /// Names the input in reports and in the headers between inputs
+    fn name(&self) -> String;
+
+    /// The file this input was read from, which is needed to modify it in
+    /// place
+    fn path(&self) -> Option<&Path> {
+        None
+    }
+
+    fn open(&self) -> Result<Box<dyn BufRead + '_>>;
+
+    /// How many bytes the input has, if that's known without reading it
+    fn size(&self) -> Option<u64> {
+        None
+    }
+
+    /// All of the input at once, for replacing across lines
+    fn contents(&self) -> Result<Contents> {
+        let mut buf = Vec::new();
+        self.open()?.read_to_end(&mut buf)?;
+        Ok(Contents::Read(buf))
+    }
+}
+
+impl Input for Source {
+    fn name(&self) -> String {
+        self.display()
+    }
+
+    fn path(&self) -> Option<&Path> {
+        match self {
+            Self::Stdin => None,
+            Self::File(path) => Some(path),
+        }
+    }
+
+    fn open(&self) -> Result<Box<dyn BufRead + '_>> {
+        open_source(self)
+    }
+
+    fn size(&self) -> Option<u64> {
+        let path = self.path()?;
+        fs::metadata(path).ok().map(|metadata| metadata.len())
+    }
+
+    fn contents(&self) -> Result<Contents> {
+        map_source(self)
+    }
+}
+
+impl<T: Input + ?Sized> Input for &T {
+    fn name(&self) -> String {
+        (**self).name()
+    }
+
+    fn path(&self) -> Option<&Path> {
+        (**self).path()
+    }
+
+    fn open(&self) -> Result<Box<dyn BufRead + '_>> {
+        (**self).open()
+    }
+
+    fn size(&self) -> Option<u64> {
+        (**self).size()
+    }
+
+    fn contents(&self) -> Result<Contents> {
+        (**self).contents()
+    }
+}
+
+impl<T: Input + ?Sized> Input for Box<T> {
+    fn name(&self) -> String {
+        (**self).name()
+    }
+
+    fn path(&self) -> Option<&Path> {
+        (**self).path()
+    }
+
+    fn open(&self) -> Result<Box<dyn BufRead + '_>> {
+        (**self).open()
+    }
+
+    fn size(&self) -> Option<u64> {
+        (**self).size()
+    }
+
+    fn contents(&self) -> Result<Contents> {
+        (**self).contents()
+    }
+}
+
+/// An input that is already in memory
+#[derive(Debug, Clone)]
+pub struct Buffer {
+    name: String,
+    data: Arc<[u8]>,
+}
+
+impl Buffer {
+    pub fn new(name: impl Into<String>, data: impl Into<Arc<[u8]>>) -> Self {
+        Self {
+            name: name.into(),
+            data: data.into(),
+        }
+    }
+}
+
+impl Input for Buffer {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn open(&self) -> Result<Box<dyn BufRead + '_>> {
+        Ok(Box::new(&*self.data))
+    }
+
+    fn size(&self) -> Option<u64> {
+        Some(self.data.len() as u64)
+    }
+
+    fn contents(&self) -> Result<Contents> {
+        Ok(Contents::Shared(Arc::clone(&self.data)))
+    }
+}
+
+/// An input that is read from a [`Read`]er, which can only happen once
+pub struct Reader<R> {
+    name: String,
+    reader: Mutex<Option<R>>,
+}
+
+impl<R: Read + Send> Reader<R> {
+    pub fn new(name: impl Into<String>, reader: R) -> Self {
+        Self {
+            name: name.into(),
+            reader: Mutex::new(Some(reader)),
+        }
+    }
+}
+
+impl<R: Read + Send> Input for Reader<R> {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn open(&self) -> Result<Box<dyn BufRead + '_>> {
+        let reader = self
+            .reader
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .take()
+            .ok_or_else(|| {
+                io::Error::other(format!("{} was already read", self.name))
+            })?;
+        Ok(Box::new(BufReader::new(reader)))
+    }
+}
@@ -1,19 +1,60 @@
+//! Find & replace with the same semantics as the `sd` command line tool
+//!
+//! The replacement engine in [`Replacer`] builds for `wasm32-unknown-unknown`
+//! without the default `io` feature. It still needs `std`, which the regex
+//! crate depends on. Reading and writing files, streams and other encodings
+//! all need `io`.
+
+#[cfg(feature = "io")]
+mod encoding;
 mod error;
+#[cfg(feature = "io")]
 mod input;
+#[cfg(feature = "async")]
+mod nonblocking;
+#[cfg(feature = "io")]
+mod options;
+#[cfg(feature = "io")]
+mod output;
+#[cfg(feature = "io")]
+mod preserve;
+#[cfg(feature = "io")]
+mod processor;
 pub mod replacer;
+#[cfg(feature = "io")]
+mod stream;
 mod unescape;
 
-use std::{
-    fs,
-    io::{BufRead, BufWriter, Read, Write},
-    path::PathBuf,
-};
+#[cfg(feature = "io")]
+use std::io::Write;
 
-pub use self::error::{Error, FailedJobs, Result};
-pub use self::input::{Source, open_source, read_source};
-pub use self::replacer::Replacer;
+#[cfg(feature = "io")]
+pub use self::encoding::TextEncoding;
+pub use self::error::{Argument, Error, FailedJobs, Result};
+#[cfg(feature = "io")]
+pub use self::input::{
+    Buffer, Contents, Input, Reader, Source, is_binary, map_source,
+    open_source, read_source,
+};
+#[cfg(feature = "async")]
+pub use self::nonblocking::replace_async;
+#[cfg(feature = "io")]
+pub use self::options::{
+    Binary, LineEnding, Preserve, ProcessOptions, RecordSeparator, Symlinks,
+    WriteMode,
+};
+#[cfg(feature = "io")]
+pub use self::processor::{
+    Callback, Entry, InPlace, Job, ParallelSink, Processor, Report, Sink,
+    Status, Writer,
+};
+pub use self::replacer::{
+    Decision, Flags, Match, ReplaceTable, Replacer, ReplacerBuilder, Visitor,
+    Warning,
+};
 
 /// Core processing function that handles file replacement
+#[cfg(feature = "io")]
 pub fn process_sources(
     replacer: &Replacer,
     sources: &[Source],
@@ -21,214 +62,42 @@ pub fn process_sources(
     line_by_line: bool,
     output_writer: &mut dyn Write,
 ) -> Result<()> {
-    if line_by_line {
-        return process_sources_line_by_line(
-            replacer,
-            sources,
-            preview,
-            output_writer,
-        );
-    }
-
-    let mut inputs = Vec::new();
-    for source in sources.iter() {
-        let input = match source {
-            Source::File(path) => {
-                if path.exists() {
-                    read_source(source)?
-                } else {
-                    return Err(Error::InvalidPath(path.to_owned()));
-                }
-            }
-            Source::Stdin => read_source(source)?,
-        };
-
-        inputs.push(input);
-    }
-
-    let needs_separator = sources.len() > 1;
-
-    let replaced: Vec<_> = {
-        use rayon::prelude::*;
-        inputs
-            .par_iter()
-            .map(|input| replacer.replace(input))
-            .collect()
+    let options = ProcessOptions {
+        preview,
+        across: !line_by_line,
+        ..Default::default()
     };
-
-    if preview || sources.first() == Some(&Source::Stdin) {
-        for (source, replaced) in sources.iter().zip(replaced) {
-            if needs_separator {
-                writeln!(output_writer, "----- {} -----", source.display())?;
-            }
-            output_writer.write_all(&replaced)?;
-        }
-    } else {
-        let mut failed_jobs = Vec::new();
-        for (source, replaced) in sources.iter().zip(replaced) {
-            match source {
-                Source::File(path) => {
-                    if let Err(e) = write_with_temp(path, &replaced) {
-                        failed_jobs.push((path.to_owned(), e));
-                    }
-                }
-                _ => unreachable!("stdin should go previous branch"),
-            }
-        }
-        if !failed_jobs.is_empty() {
-            return Err(Error::FailedJobs(FailedJobs(failed_jobs)));
-        }
-    }
-
+    process_sources_with(replacer, sources, &options, output_writer)?;
     Ok(())
 }
 
-fn process_sources_line_by_line(
+/// Like [`process_sources`], but with the full set of [`ProcessOptions`]
+///
+/// Previews and STDIN are written to `output_writer`, and files are modified
+/// in place otherwise. The report tells which files were left alone, like
+/// binary ones. An input that fails doesn't stop the others, and all of the
+/// failures are returned together as [`Error::FailedJobs`]. See
+/// [`Processor`] for other inputs and outputs.
+#[cfg(feature = "io")]
+pub fn process_sources_with(
     replacer: &Replacer,
     sources: &[Source],
-    preview: bool,
+    options: &ProcessOptions,
     output_writer: &mut dyn Write,
-) -> Result<()> {
-    let needs_separator = sources.len() > 1;
-
-    if preview || sources.first() == Some(&Source::Stdin) {
-        for source in sources {
-            if needs_separator {
-                writeln!(output_writer, "----- {} -----", source.display())?;
-            }
-            let reader = open_source(source)?;
-            process_reader_line_by_line(replacer, reader, output_writer)?;
-        }
+) -> Result<Report> {
+    let processor = Processor::new(replacer, options);
+    let report = if options.preview || sources.first() == Some(&Source::Stdin) {
+        processor.run(sources, &mut Writer::new(output_writer))?
     } else {
-        // Pre-validate all files before modifying any, matching the
-        // whole-file processing path which opens all inputs upfront.
-        for source in sources {
-            match source {
-                Source::File(path) => {
-                    if !path.exists() {
-                        return Err(Error::InvalidPath(path.to_owned()));
-                    }
-                    std::fs::File::open(path)?;
-                }
-                _ => unreachable!("stdin should go previous branch"),
-            }
-        }
-
-        let mut failed_jobs = Vec::new();
-        for source in sources {
-            match source {
-                Source::File(path) => {
-                    if let Err(e) = write_file_line_by_line(replacer, path) {
-                        failed_jobs.push((path.to_owned(), e));
-                    }
-                }
-                _ => unreachable!("stdin should go previous branch"),
-            }
-        }
-        if !failed_jobs.is_empty() {
-            return Err(Error::FailedJobs(FailedJobs(failed_jobs)));
-        }
-    }
-
-    Ok(())
-}
-
-fn process_reader_line_by_line(
-    replacer: &Replacer,
-    mut reader: Box<dyn BufRead + '_>,
-    writer: &mut dyn Write,
-) -> Result<()> {
-    const CHUNK_SIZE: usize = 8192;
-
-    let mut chunk = vec![0u8; CHUNK_SIZE];
-    let mut line = Vec::with_capacity(256);
-
-    loop {
-        let n = reader.read(&mut chunk)?;
-        if n == 0 {
-            // Finish any remaining line
-            if !line.is_empty() {
-                let replaced = replacer.replace(&line);
-                writer.write_all(&replaced)?;
-            }
-            break;
-        }
-
-        let mut start = 0;
-        for (i, &byte) in chunk[..n].iter().enumerate() {
-            if byte == b'\n' {
-                // Found a complete line
-                line.extend_from_slice(&chunk[start..i]);
-                let replaced = replacer.replace(&line);
-                writer.write_all(&replaced)?;
-                writer.write_all(b"\n")?;
-                line.clear();
-                start = i + 1;
-            }
-        }
-
-        // Keep partial line for next chunk
-        if start < n {
-            line.extend_from_slice(&chunk[start..n]);
-        }
-    }
-
-    Ok(())
-}
-
-fn write_file_line_by_line(replacer: &Replacer, path: &PathBuf) -> Result<()> {
-    let canonical = fs::canonicalize(path)?;
-
-    let temp = tempfile::NamedTempFile::new_in(
-        canonical
-            .parent()
-            .ok_or_else(|| Error::InvalidPath(canonical.to_path_buf()))?,
-    )?;
-
-    if let Ok(metadata) = fs::metadata(&canonical) {
-        temp.as_file().set_permissions(metadata.permissions()).ok();
-    }
-
-    {
-        let source = Source::File(path.clone());
-        let reader = open_source(&source)?;
-        let mut writer = BufWriter::new(temp.as_file());
-        process_reader_line_by_line(replacer, reader, &mut writer)?;
-        writer.flush()?;
-    }
-
-    temp.persist(&canonical)?;
-
-    Ok(())
-}
-
-fn write_with_temp(path: &PathBuf, data: &[u8]) -> Result<()> {
-    let path = fs::canonicalize(path)?;
-
-    let mut temp = tempfile::NamedTempFile::new_in(
-        path.parent()
-            .ok_or_else(|| Error::InvalidPath(path.to_path_buf()))?,
-    )?;
-
-    let file = temp.as_file();
-    file.set_len(data.len() as u64)?;
-    if let Ok(metadata) = fs::metadata(&path) {
-        file.set_permissions(metadata.permissions()).ok();
-    }
-
-    if !data.is_empty() {
-        temp.as_file_mut().write_all(data)?;
-        temp.as_file_mut().flush()?;
-    }
-
-    temp.persist(&path)?;
-
-    Ok(())
+        processor.run_parallel(sources, &InPlace)?
+    };
+    report.result()
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "io"))]
 mod tests {
     use super::*;
+    use std::fs;
     use tempfile::TempDir;
 
     #[test]
@@ -269,11 +138,166 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_process_sources_in_place_multiple_files() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let paths: Vec<_> = (0..4)
+            .map(|i| {
+                let path = temp_dir.path().join(format!("test{i}.txt"));
+                std::fs::write(&path, format!("abc{i}\nabc")).unwrap();
+                path
+            })
+            .collect();
+
+        let replacer =
+            Replacer::new("abc".into(), "xyz".into(), false, None, 0)?;
+        let sources: Vec<_> = paths.iter().cloned().map(Source::File).collect();
+        let mut output = Vec::new();
+
+        process_sources(&replacer, &sources, false, false, &mut output)?;
+
+        assert!(output.is_empty());
+        for (i, path) in paths.iter().enumerate() {
+            let result = std::fs::read_to_string(path).unwrap();
+            assert_eq!(result, format!("xyz{i}\nxyz"));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_sources_line_by_line_threads() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let sources: Vec<_> = (0..5)
+            .map(|i| {
+                let path = temp_dir.path().join(format!("test{i}.txt"));
+                std::fs::write(&path, format!("abc{i}\n")).unwrap();
+                Source::File(path)
+            })
+            .collect();
+
+        let replacer =
+            Replacer::new("abc".into(), "xyz".into(), false, None, 0)?;
+        let options = ProcessOptions {
+            preview: true,
+            threads: 2,
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+
+        process_sources_with(&replacer, &sources, &options, &mut output)?;
+
+        let expected: String = sources
+            .iter()
+            .enumerate()
+            .map(|(i, source)| {
+                format!("----- {} -----\nxyz{i}\n", source.display())
+            })
+            .collect();
+        assert_eq!(String::from_utf8(output).unwrap(), expected);
+
+        let options = ProcessOptions {
+            threads: 2,
+            ..Default::default()
+        };
+        process_sources_with(&replacer, &sources, &options, &mut Vec::new())?;
+        for (i, source) in sources.iter().enumerate() {
+            let Source::File(path) = source else {
+                unreachable!()
+            };
+            let result = std::fs::read_to_string(path).unwrap();
+            assert_eq!(result, format!("xyz{i}\n"));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_map_source() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("test.txt");
+        std::fs::write(&file_path, "abc123def").unwrap();
+        let empty_path = temp_dir.path().join("empty.txt");
+        std::fs::write(&empty_path, "").unwrap();
+
+        assert_eq!(&*map_source(&Source::File(file_path))?, b"abc123def");
+        assert_eq!(&*map_source(&Source::File(empty_path))?, b"");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_binary() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let check = |name: &str, contents: &[u8]| {
+            let path = temp_dir.path().join(name);
+            std::fs::write(&path, contents).unwrap();
+            is_binary(&Source::File(path), &ProcessOptions::default())
+        };
+
+        assert!(!check("text.txt", b"abc\ndef\n")?);
+        assert!(!check("empty.txt", b"")?);
+        assert!(check("image.png", b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR")?);
+        assert!(!check("utf16.txt", b"\xFF\xFEa\0b\0")?);
+        assert!(!is_binary(&Source::Stdin, &ProcessOptions::default())?);
+
+        // NUL separated records are text all the same
+        let path = temp_dir.path().join("records");
+        std::fs::write(&path, b"a\0b\0").unwrap();
+        let options = ProcessOptions {
+            record_separator: RecordSeparator::Bytes(b"\0".to_vec()),
+            ..Default::default()
+        };
+        assert!(!is_binary(&Source::File(path), &options)?);
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_preserve_xattrs() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("test.txt");
+        std::fs::write(&file_path, "abc").unwrap();
+        if xattr::set(&file_path, "user.origin", b"upstream").is_err() {
+            // Not every filesystem supports user attributes
+            return Ok(());
+        }
+
+        let replacer =
+            Replacer::new("abc".into(), "def".into(), false, None, 0).unwrap();
+        let sources = [Source::File(file_path.clone())];
+        let options = ProcessOptions {
+            preserve: Preserve {
+                xattrs: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        process_sources_with(&replacer, &sources, &options, &mut vec![])?;
+        assert_eq!(std::fs::read_to_string(&file_path)?, "def");
+        assert_eq!(
+            xattr::get(&file_path, "user.origin")?.as_deref(),
+            Some(b"upstream".as_slice())
+        );
+
+        std::fs::write(&file_path, "abc").unwrap();
+        process_sources_with(
+            &replacer,
+            &sources,
+            &ProcessOptions::default(),
+            &mut vec![],
+        )?;
+        assert_eq!(xattr::get(&file_path, "user.origin")?, None);
+
+        Ok(())
+    }
+
     #[test]
     fn test_process_sources_nonexistent_file() {
         let replacer =
             Replacer::new("abc".into(), "def".into(), false, None, 0).unwrap();
-        let nonexistent = PathBuf::from("/nonexistent/file.txt");
+        let nonexistent = std::path::PathBuf::from("/nonexistent/file.txt");
         let sources = vec![Source::File(nonexistent.clone())];
         let mut output = Vec::new();
 
@@ -288,16 +312,63 @@ mod tests {
     }
 
     #[test]
-    fn test_write_with_temp() -> Result<()> {
+    fn test_preserve_timestamps() -> Result<()> {
         let temp_dir = TempDir::new().unwrap();
-        let file_path = temp_dir.path().join("test.txt");
-        std::fs::write(&file_path, "original").unwrap();
-
-        let new_data = b"new content";
-        write_with_temp(&file_path, new_data)?;
+        let past = std::time::SystemTime::UNIX_EPOCH
+            + std::time::Duration::from_secs(1_000_000_000);
+        let set_past = |path: &std::path::Path| {
+            fs::File::options()
+                .write(true)
+                .open(path)
+                .unwrap()
+                .set_times(
+                    fs::FileTimes::new().set_accessed(past).set_modified(past),
+                )
+                .unwrap();
+        };
+        let modified = |path: &std::path::Path| {
+            fs::metadata(path).unwrap().modified().unwrap()
+        };
+        let changed = temp_dir.path().join("changed");
+        let unchanged = temp_dir.path().join("unchanged");
+        let sources = [
+            Source::File(changed.clone()),
+            Source::File(unchanged.clone()),
+        ];
 
-        let result = std::fs::read_to_string(&file_path).unwrap();
-        assert_eq!(result, "new content");
+        let replacer =
+            Replacer::new("^old".into(), "new".into(), false, None, 0).unwrap();
+        for across in [false, true] {
+            std::fs::write(&changed, "old\n").unwrap();
+            std::fs::write(&unchanged, "other\n").unwrap();
+            set_past(&changed);
+            set_past(&unchanged);
+
+            // Files that don't change aren't rewritten at all
+            let options = ProcessOptions {
+                across,
+                ..Default::default()
+            };
+            process_sources_with(&replacer, &sources, &options, &mut vec![])?;
+            assert_eq!(std::fs::read_to_string(&changed)?, "new\n");
+            assert_ne!(modified(&changed), past);
+            assert_eq!(modified(&unchanged), past);
+
+            std::fs::write(&changed, "old\n").unwrap();
+            set_past(&changed);
+            let preserve = Preserve {
+                timestamps: true,
+                ..Default::default()
+            };
+            let options = ProcessOptions {
+                across,
+                preserve,
+                ..Default::default()
+            };
+            process_sources_with(&replacer, &sources, &options, &mut vec![])?;
+            assert_eq!(std::fs::read_to_string(&changed)?, "new\n");
+            assert_eq!(modified(&changed), past);
+        }
 
         Ok(())
     }
@@ -0,0 +1,191 @@
+//! Replacing from tokio's async readers into its async writers
+
+use std::io;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::{
+    ProcessOptions, Replacer, Result,
+    encoding::EncodeWriter,
+    processor::replace_contents,
+    stream::{CHUNK_SIZE, Records},
+};
+
+/// Replaces everything read from `reader`, and writes the result to `writer`
+///
+/// Input is replaced one record at a time as it comes in, and only the
+/// current record is held in memory. With [`ProcessOptions::across`], all of
+/// it is read first and replaced at once instead. Replacing through a window
+/// needs a blocking reader, so it isn't supported here.
+///
+/// ```
+/// # tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {
+/// use sd::{ProcessOptions, ReplacerBuilder, replace_async};
+///
+/// let replacer = ReplacerBuilder::new("a", "b").build()?;
+/// let mut output = Vec::new();
+/// let options = ProcessOptions::default();
+/// replace_async(&replacer, &b"aa\n"[..], &mut output, &options).await?;
+/// assert_eq!(output, b"bb\n");
+/// # Ok::<(), sd::Error>(())
+/// # }).unwrap();
+/// ```
+pub async fn replace_async<R, W>(
+    replacer: &Replacer,
+    mut reader: R,
+    mut writer: W,
+    options: &ProcessOptions,
+) -> Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    if options.window.is_some() {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "async readers can't be replaced through a window",
+        )
+        .into());
+    }
+    replacer.check_mode(options)?;
+
+    if options.across {
+        let mut input = Vec::new();
+        reader.read_to_end(&mut input).await?;
+        let output = replace_contents(replacer, &input, None, options)?;
+        writer.write_all(&output).await?;
+        writer.flush().await?;
+        return Ok(());
+    }
+
+    // A BOM has to be read in full before the encoding can be detected
+    let mut chunk = vec![0u8; CHUNK_SIZE];
+    let mut n = 0;
+    loop {
+        let read = reader.read(&mut chunk[n..]).await?;
+        n += read;
+        if read == 0 || n >= 4 {
+            break;
+        }
+    }
+
+    let transcoding = options.encoding.detect(&chunk[..n]);
+    let mut decoder = transcoding.map(|t| t.chunk_decoder());
+    let mut encoder = transcoding
+        .map(|t| EncodeWriter::new(Vec::new(), t))
+        .transpose()?;
+    let mut records =
+        Records::new(replacer, None, &options.record_separator, options.eol);
+    let mut decoded = Vec::new();
+    let mut output = Vec::new();
+    loop {
+        let eof = n == 0;
+        let input = match &mut decoder {
+            Some(decoder) => {
+                decoded.clear();
+                decoder.decode(&chunk[..n], eof, &mut decoded)?;
+                &decoded[..]
+            }
+            None => &chunk[..n],
+        };
+        match &mut encoder {
+            Some(encoder) => {
+                records.feed(input, eof, encoder)?;
+                output = std::mem::take(encoder.get_mut());
+            }
+            None => {
+                output.clear();
+                records.feed(input, eof, &mut output)?;
+            }
+        }
+        writer.write_all(&output).await?;
+
+        if eof {
+            break;
+        }
+        n = reader.read(&mut chunk).await?;
+    }
+
+    if let Some(encoder) = encoder {
+        writer.write_all(&encoder.finish()?).await?;
+    }
+    writer.flush().await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        pin::Pin,
+        task::{Context, Poll},
+    };
+
+    use tokio::io::ReadBuf;
+
+    use super::*;
+
+    /// Hands out a single byte per read, to split records and characters
+    struct Trickle<'a>(&'a [u8]);
+
+    impl AsyncRead for Trickle<'_> {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<io::Result<()>> {
+            let n = self.0.len().min(buf.remaining()).min(1);
+            buf.put_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    fn utf16le(s: &str) -> Vec<u8> {
+        s.encode_utf16().flat_map(u16::to_le_bytes).collect()
+    }
+
+    async fn replace(
+        input: &[u8],
+        options: &ProcessOptions,
+    ) -> Result<Vec<u8>> {
+        let replacer = Replacer::new("^a".into(), "b".into(), false, None, 0)?;
+        let mut output = Vec::new();
+        replace_async(&replacer, Trickle(input), &mut output, options).await?;
+        Ok(output)
+    }
+
+    #[tokio::test]
+    async fn lines_and_across() -> Result<()> {
+        let across = ProcessOptions {
+            across: true,
+            ..Default::default()
+        };
+        assert_eq!(replace(b"aa\naa", &Default::default()).await?, b"ba\nba");
+        assert_eq!(replace(b"aa\naa", &across).await?, b"ba\nba");
+        assert_eq!(replace(b"", &Default::default()).await?, b"");
+
+        let window = ProcessOptions {
+            window: Some(16),
+            ..Default::default()
+        };
+        assert!(replace(b"a", &window).await.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn transcodes() -> Result<()> {
+        let input = [&b"\xFF\xFE"[..], &utf16le("aé\naé")].concat();
+        let expected = [&b"\xFF\xFE"[..], &utf16le("bé\nbé")].concat();
+        assert_eq!(replace(&input, &Default::default()).await?, expected);
+
+        let latin1 = ProcessOptions {
+            encoding: "latin1".parse()?,
+            ..Default::default()
+        };
+        assert_eq!(replace(b"a\xE9\na", &latin1).await?, b"b\xE9\nb");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,291 @@
+use std::{convert::Infallible, path::PathBuf, str::FromStr};
+
+use memchr::memmem;
+
+use crate::{TextEncoding, unescape};
+
+/// Settings for [`process_sources_with`](crate::process_sources_with)
+#[derive(Debug, Clone, Default)]
+pub struct ProcessOptions {
+    /// Write the results to the output writer instead of modifying files
+    /// in place
+    pub preview: bool,
+    /// Process each input as a whole rather than line by line
+    pub across: bool,
+    /// Stream the input through a buffer instead of splitting it into
+    /// lines, so that matches of up to this many bytes can span lines.
+    /// Ignored when `across` is set.
+    pub window: Option<usize>,
+    /// What separates the records (lines by default) that are matched one
+    /// at a time. Ignored when `across` or `window` is set.
+    pub record_separator: RecordSeparator,
+    /// How to write the end of each line when records are lines
+    pub eol: LineEnding,
+    /// Write the output for each file to the same relative path under this
+    /// directory instead of modifying it in place. Ignored with `preview`.
+    ///
+    /// Files skipped for looking binary are copied there as they are. The
+    /// files written there are always new ones, so `write_mode` and
+    /// `preserve` don't apply to them.
+    pub output_dir: Option<PathBuf>,
+    /// How files are modified in place
+    pub write_mode: WriteMode,
+    /// Which symlinks are followed when modifying files in place
+    pub symlinks: Symlinks,
+    /// What to keep of a file's metadata when rewriting it in place
+    pub preserve: Preserve,
+    /// How inputs are decoded before matching
+    pub encoding: TextEncoding,
+    /// What to do with files that look binary
+    pub binary: Binary,
+    /// How many files to process in parallel. 0 uses one thread per CPU
+    /// core.
+    pub threads: usize,
+}
+
+impl ProcessOptions {
+    /// Whether inputs are split into records that are matched one at a time
+    pub(crate) fn is_by_record(&self) -> bool {
+        !self.across && self.window.is_none()
+    }
+
+    /// Whether the text being matched never has a newline in it
+    pub(crate) fn is_line_by_line(&self) -> bool {
+        self.is_by_record() && self.record_separator.is_lines()
+    }
+}
+
+/// Which symlinks are followed to the file they point to when modifying
+/// files in place
+///
+/// Symlinks are never replaced themselves, so not following one means
+/// refusing to modify it. Symlinks among the directories leading up to a
+/// file are always followed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Symlinks {
+    /// Follow symlinks in the working directory that point anywhere under
+    /// it. Symlinks outside of it are only followed when they point under
+    /// the directory they are in.
+    #[default]
+    WithinRoot,
+    /// Follow all symlinks, wherever they point
+    Follow,
+    /// Don't follow any symlinks
+    NoFollow,
+}
+
+/// How the new contents of a file replace the old ones when modifying it in
+/// place
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WriteMode {
+    /// Write a temp file next to the original and rename it over it
+    ///
+    /// The swap is atomic, but the file ends up as a new inode, which breaks
+    /// hard links to it.
+    #[default]
+    Rename,
+    /// Write the new contents to a temp file elsewhere, then copy them into
+    /// the original file
+    ///
+    /// The file keeps its inode, hard links and metadata, and its directory
+    /// doesn't need to be writable. In exchange a failure while copying
+    /// leaves it partially written.
+    Truncate,
+    /// Like [`WriteMode::Rename`], but the temp file starts out as a reflink
+    /// (a copy-on-write clone) of the original, so that the unchanged parts
+    /// keep sharing storage with it
+    ///
+    /// Filesystems without reflinks fall back to a plain copy.
+    CopyOnWrite,
+}
+
+/// What a [`Processor`](crate::Processor) does with files that look binary,
+/// as decided by [`is_binary`](crate::is_binary)
+///
+/// Only inputs with a path are checked, since other ones can't be peeked at
+/// without reading them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Binary {
+    /// Leave them alone, and report them as [`Status::Skipped`]
+    ///
+    /// [`Status::Skipped`]: crate::Status::Skipped
+    #[default]
+    Skip,
+    /// Replace in them like in any other file
+    Process,
+    /// Fail with [`Error::BinaryFile`](crate::Error::BinaryFile) before
+    /// processing any input
+    Error,
+}
+
+/// File metadata kept when rewriting files in place, on top of their
+/// permissions
+///
+/// Files whose contents stay the same are never rewritten, so they keep all
+/// of it regardless.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Preserve {
+    /// Access and modification times
+    pub timestamps: bool,
+    /// Extended attributes, other than ACLs (Unix only)
+    pub xattrs: bool,
+    /// POSIX access control lists (Unix only)
+    pub acl: bool,
+}
+
+/// The boundary between records in line-by-line mode
+///
+/// Separators are never part of the text being matched, and are written
+/// back out unchanged.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RecordSeparator {
+    /// A fixed sequence of bytes, like `\n` or `\0`
+    Bytes(Vec<u8>),
+    /// One or more blank lines, so that each record is a paragraph
+    Paragraph,
+}
+
+impl Default for RecordSeparator {
+    fn default() -> Self {
+        Self::Bytes(b"\n".to_vec())
+    }
+}
+
+impl FromStr for RecordSeparator {
+    type Err = Infallible;
+
+    /// Parses backslash escapes like `\n`, `\0` or `\x1e`, with an empty
+    /// string selecting [`RecordSeparator::Paragraph`]
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() {
+            return Ok(Self::Paragraph);
+        }
+
+        // `\0` isn't an escape in replacements, so it gets expanded here.
+        // Splitting on escaped backslashes first keeps `\\0` intact.
+        let mut bytes = Vec::new();
+        for (i, part) in s.split(r"\\").enumerate() {
+            if i > 0 {
+                bytes.push(b'\\');
+            }
+            let part = part.replace(r"\0", r"\x00");
+            bytes.extend_from_slice(unescape::unescape(&part).as_bytes());
+        }
+
+        Ok(Self::Bytes(bytes))
+    }
+}
+
+impl RecordSeparator {
+    /// Whether records end in a NUL byte, which text then has plenty of
+    pub(crate) fn has_nul(&self) -> bool {
+        matches!(self, Self::Bytes(bytes) if bytes.contains(&0))
+    }
+
+    /// Whether records are lines, which is the default
+    pub(crate) fn is_lines(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Finds the first complete separator at or after `at`, returning where
+    /// it starts and ends
+    ///
+    /// A separator that runs into the end of `haystack` may still grow, so
+    /// it only counts once the input is exhausted.
+    pub(crate) fn find(
+        &self,
+        haystack: &[u8],
+        at: usize,
+        eof: bool,
+    ) -> Option<(usize, usize)> {
+        match self {
+            Self::Bytes(bytes) if bytes.is_empty() => None,
+            Self::Bytes(bytes) => memmem::find(&haystack[at..], bytes)
+                .map(|i| (at + i, at + i + bytes.len())),
+            Self::Paragraph => {
+                let start = at + memmem::find(&haystack[at..], b"\n\n")?;
+                let end = haystack[start..]
+                    .iter()
+                    .position(|&b| b != b'\n')
+                    .map(|len| start + len);
+                match end {
+                    Some(end) => Some((start, end)),
+                    None if eof => Some((start, haystack.len())),
+                    None => None,
+                }
+            }
+        }
+    }
+
+    /// Where to resume searching once more input is appended to a
+    /// `haystack` that [`Self::find`] came up empty on
+    pub(crate) fn resume_at(&self, haystack: &[u8]) -> usize {
+        let trailing = match self {
+            Self::Bytes(bytes) => bytes.len().saturating_sub(1),
+            Self::Paragraph => {
+                haystack.iter().rev().take_while(|&&b| b == b'\n').count()
+            }
+        };
+        haystack.len().saturating_sub(trailing)
+    }
+}
+
+/// The line endings written in line-by-line mode
+///
+/// Either way, the `\r` of a `\r\n` line ending is never part of the text
+/// being matched.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LineEnding {
+    /// Keep the ending each line originally had
+    #[default]
+    Preserve,
+    /// End every line with `\n`
+    Lf,
+    /// End every line with `\r\n`
+    Crlf,
+}
+
+impl LineEnding {
+    /// Splits a line that ends in `\r` (from a `\r\n` ending) into the text
+    /// to match and the line ending to write after it
+    pub(crate) fn split(self, line: &[u8]) -> (&[u8], &'static [u8]) {
+        let (line, original) = match line.strip_suffix(b"\r") {
+            Some(line) => (line, b"\r\n".as_slice()),
+            None => (line, b"\n".as_slice()),
+        };
+        let ending = match self {
+            Self::Preserve => original,
+            Self::Lf => b"\n",
+            Self::Crlf => b"\r\n",
+        };
+        (line, ending)
+    }
+
+    /// Like [`Self::split`], but for a last line that isn't followed by a
+    /// `\n`, whose `\r` is only dropped when writing `\n` endings
+    pub(crate) fn split_last(self, line: &[u8]) -> (&[u8], &'static [u8]) {
+        match line.strip_suffix(b"\r") {
+            Some(line) if self == Self::Lf => (line, b""),
+            Some(line) => (line, b"\r"),
+            None => (line, b""),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_record_separator() {
+        let parse = |s: &str| s.parse::<RecordSeparator>().unwrap();
+        let bytes = |b: &[u8]| RecordSeparator::Bytes(b.to_vec());
+
+        assert_eq!(parse(""), RecordSeparator::Paragraph);
+        assert_eq!(parse(r"\0"), bytes(b"\0"));
+        assert_eq!(parse(r"\r\n"), bytes(b"\r\n"));
+        assert_eq!(parse(r"\x1e"), bytes(b"\x1e"));
+        assert_eq!(parse(r"\\0"), bytes(b"\\0"));
+        assert_eq!(parse("---"), bytes(b"---"));
+    }
+}
@@ -1,35 +1,420 @@
-use crate::{Error, Result};
-use std::{fs, io::Write, path::Path};
+//! Writing the new contents of files that are modified in place
+//!
+//! Whichever way a file is written, it keeps its ownership and permissions,
+//! and a symlink to it stays a symlink (callers pass the path from
+//! [`resolve`], so its target is what gets written to).
 
-pub(crate) fn write_atomic(path: &Path, data: &[u8]) -> Result<()> {
-    let path = fs::canonicalize(path)?;
+use std::{
+    env,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Component, Path, PathBuf},
+};
 
-    let mut temp = tempfile::NamedTempFile::new_in(
-        path.parent()
-            .ok_or_else(|| Error::InvalidPath(path.to_path_buf()))?,
-    )?;
+use tempfile::NamedTempFile;
 
-    let file = temp.as_file();
-    file.set_len(data.len() as u64)?;
+use crate::{Error, Preserve, Result, Symlinks, WriteMode, preserve};
 
-    if let Ok(metadata) = fs::metadata(&path) {
-        file.set_permissions(metadata.permissions()).ok();
+/// Finds the file to write to for `path`, following it if it's a symlink
+/// that `symlinks` allows following
+pub(crate) fn resolve(path: &Path, symlinks: Symlinks) -> Result<PathBuf> {
+    let canonical = fs::canonicalize(path)?;
+    let is_symlink = fs::symlink_metadata(path)?.file_type().is_symlink();
 
-        // Explicitly retain ownership
-        #[cfg(unix)]
-        {
-            use std::os::unix::fs::{MetadataExt, fchown};
-            fchown(file, Some(metadata.uid()), Some(metadata.gid()))?;
-            metadata.gid();
+    match symlinks {
+        _ if !is_symlink => Ok(canonical),
+        Symlinks::Follow => Ok(canonical),
+        Symlinks::NoFollow => Err(Error::Symlink(path.to_owned())),
+        Symlinks::WithinRoot => {
+            let root = root_of(path)?;
+            if canonical.starts_with(&root) {
+                Ok(canonical)
+            } else {
+                Err(Error::SymlinkOutsideRoot(path.to_owned(), canonical, root))
+            }
+        }
+    }
+}
+
+/// The directory that the symlink at `path` may point anywhere under with
+/// [`Symlinks::WithinRoot`]: the working directory, or the directory the
+/// symlink is in when that's outside of it
+fn root_of(path: &Path) -> Result<PathBuf> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let dir = fs::canonicalize(dir)?;
+    let cwd = fs::canonicalize(env::current_dir()?)?;
+
+    Ok(if dir.starts_with(&cwd) { cwd } else { dir })
+}
+
+/// Maps `path` to the same relative path under `dir`, with absolute paths
+/// taken relative to the root
+///
+/// Paths that go up with `..` would end up outside of `dir`, so they are
+/// rejected.
+pub(crate) fn mirrored_path(dir: &Path, path: &Path) -> Result<PathBuf> {
+    let mut mirrored = dir.to_path_buf();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => mirrored.push(part),
+            Component::ParentDir => {
+                return Err(Error::InvalidPath(path.to_path_buf()));
+            }
+            Component::Prefix(_) | Component::RootDir | Component::CurDir => {}
         }
     }
 
+    Ok(mirrored)
+}
+
+/// Atomically replaces the contents of the file at `path` with `data`
+///
+/// `metadata` has to be taken before the file is read, for
+/// [`Preserve::timestamps`] to keep its access time.
+pub(crate) fn write_atomic(
+    path: &Path,
+    data: &[u8],
+    metadata: &fs::Metadata,
+    preserve: Preserve,
+) -> Result<()> {
+    let path = fs::canonicalize(path)?;
+
+    let mut temp = NamedTempFile::new_in(
+        path.parent()
+            .ok_or_else(|| Error::InvalidPath(path.to_path_buf()))?,
+    )?;
+
+    temp.as_file().set_len(data.len() as u64)?;
     if !data.is_empty() {
         temp.as_file_mut().write_all(data)?;
         temp.as_file_mut().flush()?;
     }
 
-    temp.persist(&path)?;
+    replace_with(temp, &path, metadata, preserve)
+}
+
+/// Gives `temp` the metadata of the file at `path`, then renames it over
+/// that file
+fn replace_with(
+    temp: NamedTempFile,
+    path: &Path,
+    metadata: &fs::Metadata,
+    preserve: Preserve,
+) -> Result<()> {
+    preserve::copy_metadata(path, metadata, temp.as_file(), preserve)?;
+    temp.persist(path)?;
 
     Ok(())
 }
+
+/// Takes the new contents of a file and compares them to its current ones as
+/// they are written, only writing them to a temp file once they start to
+/// differ
+///
+/// Files that come out the same are left alone entirely, without even a temp
+/// file briefly showing up next to them.
+pub(crate) struct ChangedFile<'a> {
+    path: &'a Path,
+    mode: WriteMode,
+    original: BufReader<File>,
+    /// How many bytes at the start are the same as in the original
+    same: u64,
+    /// How many bytes of new contents there are so far
+    len: u64,
+    temp: Option<BufWriter<NamedTempFile>>,
+}
+
+impl<'a> ChangedFile<'a> {
+    pub(crate) fn new(path: &'a Path, mode: WriteMode) -> io::Result<Self> {
+        Ok(Self {
+            path,
+            mode,
+            original: BufReader::new(File::open(path)?),
+            same: 0,
+            len: 0,
+            temp: None,
+        })
+    }
+
+    /// Returns the new contents, or `None` if they are the same as the
+    /// original
+    pub(crate) fn finish(mut self) -> io::Result<Option<Changes>> {
+        if self.temp.is_none() && !self.original.fill_buf()?.is_empty() {
+            // The original has more to it than the new contents
+            self.start_temp()?;
+        }
+        let Some(temp) = self.temp else {
+            return Ok(None);
+        };
+
+        let temp = temp.into_inner().map_err(|e| e.into_error())?;
+        if self.mode == WriteMode::CopyOnWrite {
+            // Cut off what's left of the cloned original
+            temp.as_file().set_len(self.len)?;
+        }
+
+        Ok(Some(Changes {
+            mode: self.mode,
+            temp,
+            same: self.same,
+            len: self.len,
+        }))
+    }
+
+    /// Creates the temp file, after which only what's written to it is
+    /// still missing
+    fn start_temp(&mut self) -> io::Result<&mut BufWriter<NamedTempFile>> {
+        let dir = self.path.parent().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "no parent directory")
+        })?;
+        let original = self.path;
+        let temp = match self.mode {
+            WriteMode::Rename => {
+                let mut temp = NamedTempFile::new_in(dir)?;
+                io::copy(
+                    &mut File::open(original)?.take(self.same),
+                    &mut temp,
+                )?;
+                temp
+            }
+            // Only the part that changed gets copied back later
+            WriteMode::Truncate => NamedTempFile::new()?,
+            WriteMode::CopyOnWrite => {
+                let mut temp =
+                    tempfile::Builder::new().make_in(dir, |path| {
+                        reflink_copy::reflink_or_copy(original, path)?;
+                        File::options().write(true).open(path)
+                    })?;
+                temp.seek(SeekFrom::Start(self.same))?;
+                temp
+            }
+        };
+
+        Ok(self.temp.insert(BufWriter::new(temp)))
+    }
+}
+
+impl Write for ChangedFile<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.len += buf.len() as u64;
+        if let Some(temp) = &mut self.temp {
+            temp.write_all(buf)?;
+            return Ok(buf.len());
+        }
+
+        let mut rest = buf;
+        while !rest.is_empty() {
+            let original = self.original.fill_buf()?;
+            let n = original.len().min(rest.len());
+            if n == 0 || original[..n] != rest[..n] {
+                self.start_temp()?.write_all(rest)?;
+                break;
+            }
+            self.original.consume(n);
+            self.same += n as u64;
+            rest = &rest[n..];
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.temp {
+            Some(temp) => temp.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// New contents for a file, ready to be saved over it
+pub(crate) struct Changes {
+    mode: WriteMode,
+    /// With [`WriteMode::Truncate`], only what follows `same`
+    temp: NamedTempFile,
+    same: u64,
+    len: u64,
+}
+
+impl Changes {
+    /// Replaces the contents of the file at `path`, whose `metadata` was
+    /// taken before it was read
+    pub(crate) fn save(
+        self,
+        path: &Path,
+        metadata: &fs::Metadata,
+        preserve: Preserve,
+    ) -> Result<()> {
+        if self.mode != WriteMode::Truncate {
+            return replace_with(self.temp, path, metadata, preserve);
+        }
+
+        let mut file = File::options().write(true).open(path)?;
+        let mut changed = self.temp.reopen()?;
+        let mut copy = || -> io::Result<()> {
+            file.seek(SeekFrom::Start(self.same))?;
+            io::copy(&mut changed, &mut file)?;
+            file.set_len(self.len)?;
+            file.flush()
+        };
+        copy().map_err(Error::PartialWrite)?;
+        if preserve.timestamps {
+            preserve::restore_timestamps(metadata, &file)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rewrite(
+        mode: WriteMode,
+        original: &str,
+        chunks: &[&str],
+    ) -> Option<String> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file");
+        std::fs::write(&path, original).unwrap();
+        let metadata = fs::metadata(&path).unwrap();
+
+        let mut file = ChangedFile::new(&path, mode).unwrap();
+        for chunk in chunks {
+            file.write_all(chunk.as_bytes()).unwrap();
+        }
+        let changes = file.finish().unwrap()?;
+        changes.save(&path, &metadata, Preserve::default()).unwrap();
+        Some(std::fs::read_to_string(&path).unwrap())
+    }
+
+    #[test]
+    fn only_writes_changes() {
+        for mode in [
+            WriteMode::Rename,
+            WriteMode::Truncate,
+            WriteMode::CopyOnWrite,
+        ] {
+            assert_eq!(rewrite(mode, "abc", &["a", "bc"]), None);
+            assert_eq!(rewrite(mode, "", &[]), None);
+            assert_eq!(rewrite(mode, "abc", &["a", "bd"]), Some("abd".into()));
+            assert_eq!(rewrite(mode, "abc", &["ab"]), Some("ab".into()));
+            assert_eq!(
+                rewrite(mode, "abc", &["ab", "cd"]),
+                Some("abcd".into())
+            );
+            assert_eq!(rewrite(mode, "abc", &["x"]), Some("x".into()));
+            assert_eq!(rewrite(mode, "abc", &[]), Some("".into()));
+        }
+    }
+
+    #[test]
+    fn mirrored_paths() {
+        let mirror =
+            |path: &str| mirrored_path(Path::new("out"), Path::new(path));
+
+        assert_eq!(mirror("src/lib.rs").unwrap(), Path::new("out/src/lib.rs"));
+        assert_eq!(mirror("./a/./b").unwrap(), Path::new("out/a/b"));
+        #[cfg(unix)]
+        assert_eq!(mirror("/etc/hosts").unwrap(), Path::new("out/etc/hosts"));
+        assert!(mirror("../secret").is_err());
+        assert!(mirror("a/../../b").is_err());
+    }
+
+    #[test]
+    fn write_atomic_replaces_contents() -> Result<()> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file");
+        std::fs::write(&path, "original").unwrap();
+
+        let metadata = fs::metadata(&path)?;
+        write_atomic(&path, b"new content", &metadata, Preserve::default())?;
+        assert_eq!(std::fs::read_to_string(&path)?, "new content");
+
+        Ok(())
+    }
+
+    #[test]
+    fn truncate_keeps_inode() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file");
+        let link = dir.path().join("link");
+        std::fs::write(&path, "abc").unwrap();
+        std::fs::hard_link(&path, &link).unwrap();
+        let metadata = fs::metadata(&path).unwrap();
+
+        let mut file = ChangedFile::new(&path, WriteMode::Truncate).unwrap();
+        file.write_all(b"xyz").unwrap();
+        let changes = file.finish().unwrap().unwrap();
+        changes.save(&path, &metadata, Preserve::default()).unwrap();
+
+        assert_eq!(std::fs::read_to_string(&link).unwrap(), "xyz");
+    }
+
+    /// Rewrites files with every combination of mode and write mode, and
+    /// checks that their owner, group and permissions stay the same
+    #[cfg(unix)]
+    #[test]
+    fn keeps_ownership() -> Result<()> {
+        use std::os::unix::fs::{MetadataExt, PermissionsExt, chown};
+
+        use crate::{ProcessOptions, Replacer, Source, process_sources_with};
+
+        let dir = tempfile::tempdir().unwrap();
+        let probe = dir.path().join("probe");
+        std::fs::write(&probe, "")?;
+        if fs::metadata(&probe)?.uid() != 0 {
+            // Handing files to other users takes root
+            return Ok(());
+        }
+
+        let replacer =
+            Replacer::new("old".into(), "new".into(), false, None, 0).unwrap();
+        let cases = [
+            ("root-owned", 0, 0, 0o644),
+            ("other user", 4321, 4321, 0o600),
+            ("group-shared", 4321, 4322, 0o2664),
+        ];
+        for (name, uid, gid, mode) in cases {
+            for across in [false, true] {
+                for write_mode in [
+                    WriteMode::Rename,
+                    WriteMode::Truncate,
+                    WriteMode::CopyOnWrite,
+                ] {
+                    let path = dir.path().join(name);
+                    std::fs::write(&path, "old\n")?;
+                    chown(&path, Some(uid), Some(gid))?;
+                    fs::set_permissions(
+                        &path,
+                        fs::Permissions::from_mode(mode),
+                    )?;
+
+                    let options = ProcessOptions {
+                        across,
+                        write_mode,
+                        ..Default::default()
+                    };
+                    process_sources_with(
+                        &replacer,
+                        &[Source::File(path.clone())],
+                        &options,
+                        &mut vec![],
+                    )?;
+
+                    let context = format!("{name}, {write_mode:?}, {across}");
+                    let metadata = fs::metadata(&path)?;
+                    assert_eq!(std::fs::read_to_string(&path)?, "new\n");
+                    assert_eq!(metadata.uid(), uid, "{context}");
+                    assert_eq!(metadata.gid(), gid, "{context}");
+                    assert_eq!(metadata.mode() & 0o7777, mode, "{context}");
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,91 @@
+use std::{
+    fs::{self, File, FileTimes},
+    path::Path,
+};
+
+use crate::{Preserve, Result};
+
+/// Copies the ownership and permissions of the `original` file, along with
+/// whatever else `preserve` asks for, to the `temp` file about to replace it
+///
+/// `metadata` has to be taken before the original is read, so that reading
+/// it doesn't count as an access. Timestamps are copied last, since any
+/// write to `temp` after that would update them again.
+pub(crate) fn copy_metadata(
+    original: &Path,
+    metadata: &fs::Metadata,
+    temp: &File,
+    preserve: Preserve,
+) -> Result<()> {
+    // Changing the owner clears setuid and setgid bits, so it goes first
+    #[cfg(unix)]
+    copy_ownership(metadata, temp);
+    temp.set_permissions(metadata.permissions()).ok();
+
+    if preserve.xattrs || preserve.acl {
+        copy_xattrs(original, temp, preserve)?;
+    }
+
+    if preserve.timestamps {
+        restore_timestamps(metadata, temp)?;
+    }
+
+    Ok(())
+}
+
+/// Sets the access and modification times of `file` back to the ones in
+/// `metadata`
+pub(crate) fn restore_timestamps(
+    metadata: &fs::Metadata,
+    file: &File,
+) -> Result<()> {
+    file.set_times(
+        FileTimes::new()
+            .set_accessed(metadata.accessed()?)
+            .set_modified(metadata.modified()?),
+    )?;
+
+    Ok(())
+}
+
+/// Gives `temp` the owner and group in `metadata`, as far as we're allowed to
+///
+/// Only root can hand files to other users, but anyone can keep the group of
+/// a group-shared file they are a member of.
+#[cfg(unix)]
+fn copy_ownership(metadata: &fs::Metadata, temp: &File) {
+    use std::os::unix::fs::{MetadataExt, fchown};
+
+    if fchown(temp, Some(metadata.uid()), Some(metadata.gid())).is_err() {
+        fchown(temp, None, Some(metadata.gid())).ok();
+    }
+}
+
+#[cfg(unix)]
+fn copy_xattrs(original: &Path, temp: &File, preserve: Preserve) -> Result<()> {
+    use xattr::FileExt;
+
+    for name in xattr::list(original)? {
+        // POSIX ACLs are stored as these two attributes on Linux
+        let is_acl = name
+            .to_str()
+            .is_some_and(|name| name.starts_with("system.posix_acl_"));
+        if (is_acl && !preserve.acl) || (!is_acl && !preserve.xattrs) {
+            continue;
+        }
+        if let Some(value) = xattr::get(original, &name)? {
+            temp.set_xattr(&name, &value)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn copy_xattrs(_: &Path, _: &File, _: Preserve) -> Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "extended attributes and ACLs can only be preserved on Unix",
+    )
+    .into())
+}
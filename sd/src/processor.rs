@@ -0,0 +1,889 @@
+//! Running a [`Replacer`] over many inputs, and handing what comes out to a
+//! sink
+
+use std::{
+    borrow::Cow,
+    fs,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    Binary, Contents, Error, FailedJobs, Input, ProcessOptions, Replacer,
+    Result, Source, WriteMode,
+    input::is_binary_file,
+    output::{self, ChangedFile, write_atomic},
+    replacer::ReplaceState,
+    stream,
+};
+
+/// The biggest input that is replaced ahead of its turn, and held in memory
+/// until then
+const MAX_BUFFERED: u64 = 4 << 20;
+
+/// Runs a [`Replacer`] over a list of [`Input`]s
+///
+/// ```
+/// use sd::{Buffer, Callback, ProcessOptions, Processor, ReplacerBuilder};
+///
+/// let replacer = ReplacerBuilder::new("foo", "bar").build()?;
+/// let options = ProcessOptions {
+///     across: true,
+///     ..Default::default()
+/// };
+/// let inputs = [Buffer::new("a.rs", b"foo()".to_vec())];
+///
+/// let mut generated = Vec::new();
+/// let report = Processor::new(&replacer, &options).run(
+///     &inputs,
+///     &mut Callback::new(|input, replaced| {
+///         generated.push((input.name(), replaced.to_vec()));
+///         Ok(())
+///     }),
+/// )?;
+/// report.result()?;
+/// assert_eq!(generated, [("a.rs".to_string(), b"bar()".to_vec())]);
+/// # Ok::<(), sd::Error>(())
+/// ```
+pub struct Processor<'a> {
+    replacer: &'a Replacer,
+    options: &'a ProcessOptions,
+}
+
+impl<'a> Processor<'a> {
+    pub fn new(replacer: &'a Replacer, options: &'a ProcessOptions) -> Self {
+        Self { replacer, options }
+    }
+
+    /// Hands the replaced contents of every input to `sink`, in order
+    ///
+    /// With more than one input and thread, a few of them at a time are
+    /// replaced in parallel and kept in memory until it's their turn. Other
+    /// inputs are streamed straight to the sink, like ones of more than a few
+    /// megabytes or of unknown size.
+    pub fn run<I: Input>(
+        &self,
+        inputs: &[I],
+        sink: &mut dyn Sink,
+    ) -> Result<Report> {
+        self.replacer.check_mode(self.options)?;
+        let pool = self.pool()?;
+        let all: Vec<&dyn Input> =
+            inputs.iter().map(|input| input as &dyn Input).collect();
+        let (inputs, skipped) = self.screen(&all)?;
+        sink.prepare(&inputs, self.options)?;
+
+        let mut entries = Vec::with_capacity(inputs.len());
+        let threads = pool
+            .as_ref()
+            .map_or_else(rayon::current_num_threads, |pool| {
+                pool.current_num_threads()
+            });
+        if inputs.len() == 1 || threads == 1 {
+            for &input in &inputs {
+                let result = sink.write(&self.job(input, None));
+                entries.push(Entry::new(input, result));
+            }
+            return Ok(Report::merge(&all, &skipped, entries, |input| {
+                sink.skip(input, self.options)
+            }));
+        }
+
+        // Render a batch of inputs in parallel, then hand them over in order.
+        // This keeps the output stable while bounding how much is buffered.
+        // Inputs that are big or of unknown size are streamed once it's their
+        // turn instead.
+        for batch in inputs.chunks(threads) {
+            let rendered: Vec<_> = install(pool.as_ref(), || {
+                use rayon::prelude::*;
+                batch
+                    .par_iter()
+                    .map(|&input| {
+                        let size = input.size()?;
+                        (size <= MAX_BUFFERED).then(|| {
+                            let mut output = Vec::new();
+                            self.job(input, None)
+                                .write_to(&mut output)
+                                .map(|()| output)
+                        })
+                    })
+                    .collect()
+            });
+            for (&input, rendered) in batch.iter().zip(rendered) {
+                let result = match rendered {
+                    Some(rendered) => rendered.and_then(|output| {
+                        sink.write(&self.job(input, Some(output)))
+                    }),
+                    None => sink.write(&self.job(input, None)),
+                };
+                entries.push(Entry::new(input, result));
+            }
+        }
+
+        Ok(Report::merge(&all, &skipped, entries, |input| {
+            sink.skip(input, self.options)
+        }))
+    }
+
+    /// Hands every input to `sink` on as many threads as there are to spare
+    pub fn run_parallel<I: Input>(
+        &self,
+        inputs: &[I],
+        sink: &dyn ParallelSink,
+    ) -> Result<Report> {
+        self.replacer.check_mode(self.options)?;
+        let pool = self.pool()?;
+        let all: Vec<&dyn Input> =
+            inputs.iter().map(|input| input as &dyn Input).collect();
+        let (inputs, skipped) = self.screen(&all)?;
+        sink.prepare(&inputs, self.options)?;
+
+        let entries = install(pool.as_ref(), || {
+            use rayon::prelude::*;
+            inputs
+                .par_iter()
+                .map(|&input| {
+                    Entry::new(input, sink.write(&self.job(input, None)))
+                })
+                .collect()
+        });
+
+        Ok(Report::merge(&all, &skipped, entries, |input| {
+            sink.skip(input, self.options)
+        }))
+    }
+
+    /// Leaves out the files that look binary according to
+    /// [`ProcessOptions::binary`], returning the inputs that are left along
+    /// with whether each input was skipped
+    ///
+    /// Files that can't be read are left for processing to report.
+    fn screen<'i>(
+        &self,
+        inputs: &[&'i dyn Input],
+    ) -> Result<(Vec<&'i dyn Input>, Vec<bool>)> {
+        let mut kept = Vec::with_capacity(inputs.len());
+        let mut skipped = Vec::with_capacity(inputs.len());
+        for &input in inputs {
+            let binary = match (self.options.binary, input.path()) {
+                (Binary::Process, _) | (_, None) => false,
+                (mode, Some(path)) => {
+                    let binary =
+                        is_binary_file(path, self.options).unwrap_or_default();
+                    if binary && mode == Binary::Error {
+                        return Err(Error::BinaryFile(path.to_owned()));
+                    }
+                    binary
+                }
+            };
+            if !binary {
+                kept.push(input);
+            }
+            skipped.push(binary);
+        }
+
+        Ok((kept, skipped))
+    }
+
+    fn job(&self, input: &'a dyn Input, rendered: Option<Vec<u8>>) -> Job<'a> {
+        Job {
+            input,
+            replacer: self.replacer,
+            options: self.options,
+            rendered,
+        }
+    }
+
+    fn pool(&self) -> Result<Option<rayon::ThreadPool>> {
+        Ok(match self.options.threads {
+            0 => None,
+            threads => Some(
+                rayon::ThreadPoolBuilder::new()
+                    .num_threads(threads)
+                    .build()?,
+            ),
+        })
+    }
+}
+
+/// Runs `f` on `pool`, or on the global thread pool without one
+fn install<R: Send>(
+    pool: Option<&rayon::ThreadPool>,
+    f: impl FnOnce() -> R + Send,
+) -> R {
+    match pool {
+        Some(pool) => pool.install(f),
+        None => f(),
+    }
+}
+
+/// A single input on its way through a [`Processor`]
+pub struct Job<'a> {
+    input: &'a dyn Input,
+    replacer: &'a Replacer,
+    options: &'a ProcessOptions,
+    /// The replaced contents, if they were already worked out in parallel
+    rendered: Option<Vec<u8>>,
+}
+
+impl Job<'_> {
+    pub fn input(&self) -> &dyn Input {
+        self.input
+    }
+
+    pub fn options(&self) -> &ProcessOptions {
+        self.options
+    }
+
+    /// Writes the replaced contents of the input to `writer`
+    pub fn write_to(&self, writer: &mut dyn Write) -> Result<()> {
+        match &self.rendered {
+            Some(rendered) => Ok(writer.write_all(rendered)?),
+            None => {
+                write_input(self.replacer, self.input, self.options, writer)
+            }
+        }
+    }
+
+    /// The replaced contents of the input, all at once
+    pub fn replaced(&self) -> Result<Cow<'_, [u8]>> {
+        if let Some(rendered) = &self.rendered {
+            return Ok(Cow::Borrowed(rendered));
+        }
+
+        let mut output = Vec::new();
+        self.write_to(&mut output)?;
+        Ok(Cow::Owned(output))
+    }
+}
+
+/// Where the replaced contents of each input go when run through
+/// [`Processor::run`], one input at a time and in order
+pub trait Sink {
+    /// Gets a look at all the inputs before any of them is written
+    fn prepare(
+        &mut self,
+        _inputs: &[&dyn Input],
+        _options: &ProcessOptions,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    fn write(&mut self, job: &Job<'_>) -> Result<Status>;
+
+    /// Gets an input that is left alone for looking binary, see
+    /// [`ProcessOptions::binary`]
+    fn skip(
+        &mut self,
+        _input: &dyn Input,
+        _options: &ProcessOptions,
+    ) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Like [`Sink`], but takes inputs on several threads at once when run
+/// through [`Processor::run_parallel`]
+pub trait ParallelSink: Sync {
+    /// Gets a look at all the inputs before any of them is written, and can
+    /// refuse to start by failing
+    fn prepare(
+        &self,
+        _inputs: &[&dyn Input],
+        _options: &ProcessOptions,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    fn write(&self, job: &Job<'_>) -> Result<Status>;
+
+    /// Gets an input that is left alone for looking binary, see
+    /// [`ProcessOptions::binary`]
+    fn skip(
+        &self,
+        _input: &dyn Input,
+        _options: &ProcessOptions,
+    ) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// What happened to an input that was processed without errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// The replaced contents were handed on, like to a writer or a callback
+    Written,
+    /// The input was modified in place
+    Modified,
+    /// Nothing was replaced, so the input was left alone
+    Unchanged,
+    /// The input looked binary, so it was left alone without being read,
+    /// see [`ProcessOptions::binary`]
+    Skipped,
+}
+
+/// The outcome of processing a single input
+#[derive(Debug)]
+pub struct Entry {
+    pub name: String,
+    pub path: Option<PathBuf>,
+    pub result: Result<Status>,
+}
+
+impl Entry {
+    fn new(input: &dyn Input, result: Result<Status>) -> Self {
+        Self {
+            name: input.name(),
+            path: input.path().map(Path::to_owned),
+            result,
+        }
+    }
+}
+
+/// What came of every input given to a [`Processor`], in the same order
+#[derive(Debug, Default)]
+pub struct Report {
+    pub entries: Vec<Entry>,
+}
+
+impl Report {
+    /// Puts the `entries` of the inputs that were processed back in order
+    /// among the ones that were `skipped`, which are handed to `skip`
+    fn merge(
+        inputs: &[&dyn Input],
+        skipped: &[bool],
+        entries: Vec<Entry>,
+        mut skip: impl FnMut(&dyn Input) -> Result<()>,
+    ) -> Self {
+        let mut entries = entries.into_iter();
+        let entries = inputs
+            .iter()
+            .zip(skipped)
+            .filter_map(|(&input, &skipped)| match skipped {
+                true => {
+                    let result = skip(input).map(|()| Status::Skipped);
+                    Some(Entry::new(input, result))
+                }
+                false => entries.next(),
+            })
+            .collect();
+        Self { entries }
+    }
+
+    /// The inputs that were modified in place
+    pub fn modified(&self) -> impl Iterator<Item = &Entry> {
+        self.entries
+            .iter()
+            .filter(|entry| matches!(entry.result, Ok(Status::Modified)))
+    }
+
+    /// The inputs that were skipped for looking binary
+    pub fn skipped(&self) -> impl Iterator<Item = &Entry> {
+        self.entries
+            .iter()
+            .filter(|entry| matches!(entry.result, Ok(Status::Skipped)))
+    }
+
+    /// The inputs that failed, along with why
+    pub fn failed(&self) -> impl Iterator<Item = (&Entry, &Error)> {
+        self.entries
+            .iter()
+            .filter_map(|entry| entry.result.as_ref().err().map(|e| (entry, e)))
+    }
+
+    /// Fails with every error in the report, if there are any, or hands it
+    /// back otherwise
+    pub fn result(self) -> Result<Self> {
+        if self.failed().next().is_none() {
+            return Ok(self);
+        }
+
+        let failed_jobs: Vec<_> = self
+            .entries
+            .into_iter()
+            .filter_map(|entry| {
+                let error = entry.result.err()?;
+                let path = entry.path.unwrap_or_else(|| entry.name.into());
+                Some((path, error))
+            })
+            .collect();
+        Err(Error::FailedJobs(FailedJobs(failed_jobs)))
+    }
+}
+
+/// Writes the replaced contents of every input to a single writer, with a
+/// header naming each input when there is more than one
+pub struct Writer<W> {
+    writer: W,
+    headers: bool,
+}
+
+impl<W: Write> Writer<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            headers: false,
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write> Sink for Writer<W> {
+    fn prepare(
+        &mut self,
+        inputs: &[&dyn Input],
+        _options: &ProcessOptions,
+    ) -> Result<()> {
+        self.headers = inputs.len() > 1;
+        Ok(())
+    }
+
+    fn write(&mut self, job: &Job<'_>) -> Result<Status> {
+        if self.headers {
+            writeln!(self.writer, "----- {} -----", job.input().name())?;
+        }
+        job.write_to(&mut self.writer)?;
+        Ok(Status::Written)
+    }
+}
+
+/// Calls a function with the replaced contents of each input
+pub struct Callback<F>(F);
+
+impl<F: FnMut(&dyn Input, &[u8]) -> Result<()>> Callback<F> {
+    pub fn new(f: F) -> Self {
+        Self(f)
+    }
+}
+
+impl<F: FnMut(&dyn Input, &[u8]) -> Result<()>> Sink for Callback<F> {
+    fn write(&mut self, job: &Job<'_>) -> Result<Status> {
+        (self.0)(job.input(), &job.replaced()?)?;
+        Ok(Status::Written)
+    }
+}
+
+/// Writes the replaced contents of files back to them, or to a mirrored
+/// tree under [`ProcessOptions::output_dir`]
+///
+/// Every input has to be a file, and all of them are checked before any is
+/// modified.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct InPlace;
+
+impl ParallelSink for InPlace {
+    fn prepare(
+        &self,
+        inputs: &[&dyn Input],
+        options: &ProcessOptions,
+    ) -> Result<()> {
+        for input in inputs {
+            let path = file_path(*input)?;
+            if !path.exists() {
+                return Err(Error::InvalidPath(path.to_owned()));
+            }
+            fs::File::open(path)?;
+            if options.output_dir.is_none() {
+                output::resolve(path, options.symlinks)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn write(&self, job: &Job<'_>) -> Result<Status> {
+        let path = file_path(job.input)?;
+        let (replacer, options) = (job.replacer, job.options);
+        match &options.output_dir {
+            Some(dir) => write_file_to(replacer, path, dir, options),
+            None if options.across => {
+                write_file_across(replacer, path, options)
+            }
+            None => write_file_streaming(replacer, path, options),
+        }
+    }
+
+    /// Copies skipped files to [`ProcessOptions::output_dir`] as they are,
+    /// so that it mirrors all of the inputs
+    fn skip(&self, input: &dyn Input, options: &ProcessOptions) -> Result<()> {
+        if let Some(dir) = &options.output_dir {
+            let path = file_path(input)?;
+            fs::copy(path, mirror(dir, path)?)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The path of an input that has to be a file
+fn file_path(input: &dyn Input) -> Result<&Path> {
+    input
+        .path()
+        .ok_or_else(|| Error::InvalidPath(input.name().into()))
+}
+
+/// Writes the replaced contents of a single input to `writer`
+fn write_input(
+    replacer: &Replacer,
+    input: &dyn Input,
+    options: &ProcessOptions,
+    writer: &mut dyn Write,
+) -> Result<()> {
+    if let Some(path) = input.path() {
+        if !path.exists() {
+            return Err(Error::InvalidPath(path.to_owned()));
+        }
+    }
+
+    let path = input.path();
+    if options.across {
+        let input = input.contents()?;
+        writer
+            .write_all(&replace_contents(replacer, &input, path, options)?)?;
+    } else {
+        let reader = input.open()?;
+        stream::process_reader(replacer, reader, path, options, writer)?;
+    }
+
+    Ok(())
+}
+
+/// Replaces all of `input`, decoding it first if it's in another encoding
+pub(crate) fn replace_contents<'a>(
+    replacer: &Replacer,
+    input: &'a [u8],
+    path: Option<&Path>,
+    options: &ProcessOptions,
+) -> Result<Cow<'a, [u8]>> {
+    let mut state = ReplaceState::at(path, 0, 0);
+    let Some(transcoding) = options.encoding.detect(input) else {
+        let replaced = replacer.replace_in(input, &mut state);
+        state.check()?;
+        return Ok(replaced);
+    };
+
+    let decoded = transcoding.decode(input)?;
+    let replaced = replacer.replace_in(&decoded, &mut state);
+    state.check()?;
+    Ok(match replaced {
+        // Leave the input exactly as it was
+        Cow::Borrowed(_) => Cow::Borrowed(input),
+        Cow::Owned(replaced) => Cow::Owned(transcoding.encode(&replaced)?),
+    })
+}
+
+/// The same relative path as `path` under `dir`, along with the directories
+/// leading up to it
+fn mirror(dir: &Path, path: &Path) -> Result<PathBuf> {
+    let dest = output::mirrored_path(dir, path)?;
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    Ok(dest)
+}
+
+/// Writes the replaced contents of the file at `path` to the same relative
+/// path under `dir`, leaving the original alone
+fn write_file_to(
+    replacer: &Replacer,
+    path: &Path,
+    dir: &Path,
+    options: &ProcessOptions,
+) -> Result<Status> {
+    let dest = mirror(dir, path)?;
+    let parent = dest
+        .parent()
+        .ok_or_else(|| Error::InvalidPath(dest.clone()))?;
+
+    let temp = tempfile::NamedTempFile::new_in(parent)?;
+    {
+        let source = Source::File(path.to_path_buf());
+        let mut writer = BufWriter::new(temp.as_file());
+        write_input(replacer, &source, options, &mut writer)?;
+        writer.flush()?;
+    }
+    temp.as_file()
+        .set_permissions(fs::metadata(path)?.permissions())
+        .ok();
+    temp.persist(&dest)?;
+
+    Ok(Status::Written)
+}
+
+/// Replaces a whole file at once
+///
+/// Each file is read, replaced and written back on its own, so only the
+/// files currently being worked on are held in memory.
+fn write_file_across(
+    replacer: &Replacer,
+    path: &Path,
+    options: &ProcessOptions,
+) -> Result<Status> {
+    let canonical = output::resolve(path, options.symlinks)?;
+    let metadata = fs::metadata(&canonical)?;
+    let changes = {
+        let input: Contents = Source::File(canonical.clone()).contents()?;
+        let output = replace_contents(replacer, &input, Some(path), options)?;
+        if *output == *input {
+            return Ok(Status::Unchanged);
+        }
+        if options.write_mode == WriteMode::Rename {
+            write_atomic(&canonical, &output, &metadata, options.preserve)?;
+            return Ok(Status::Modified);
+        }
+
+        let mut writer = ChangedFile::new(&canonical, options.write_mode)?;
+        writer.write_all(&output)?;
+        writer.finish()?
+        // `input` has to be unmapped before the file gets truncated
+    };
+
+    save(changes, &canonical, &metadata, options)
+}
+
+fn write_file_streaming(
+    replacer: &Replacer,
+    path: &Path,
+    options: &ProcessOptions,
+) -> Result<Status> {
+    let canonical = output::resolve(path, options.symlinks)?;
+    let metadata = fs::metadata(&canonical)?;
+
+    if canonical.parent().is_none() {
+        return Err(Error::InvalidPath(canonical));
+    }
+
+    let mut writer = ChangedFile::new(&canonical, options.write_mode)?;
+    let source = Source::File(canonical.clone());
+    let reader = source.open()?;
+    stream::process_reader(replacer, reader, Some(path), options, &mut writer)?;
+
+    save(writer.finish()?, &canonical, &metadata, options)
+}
+
+fn save(
+    changes: Option<output::Changes>,
+    path: &Path,
+    metadata: &fs::Metadata,
+    options: &ProcessOptions,
+) -> Result<Status> {
+    match changes {
+        Some(changes) => {
+            changes.save(path, metadata, options.preserve)?;
+            Ok(Status::Modified)
+        }
+        None => Ok(Status::Unchanged),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Buffer, Reader};
+
+    #[test]
+    fn writes_inputs_in_order() -> Result<()> {
+        let replacer = Replacer::new("a".into(), "b".into(), false, None, 0)?;
+        let options = ProcessOptions::default();
+        let inputs: Vec<Box<dyn Input>> = vec![
+            Box::new(Buffer::new("first", b"aa\n".to_vec())),
+            Box::new(Reader::new("second", &b"ca\n"[..])),
+        ];
+
+        let mut writer = Writer::new(Vec::new());
+        let report =
+            Processor::new(&replacer, &options).run(&inputs, &mut writer)?;
+        assert!(report.failed().next().is_none());
+        assert_eq!(
+            writer.into_inner(),
+            b"----- first -----\nbb\n----- second -----\ncb\n"
+        );
+
+        // A reader can't be read again
+        let report = Processor::new(&replacer, &options)
+            .run(&inputs, &mut Writer::new(Vec::new()))?;
+        assert_eq!(
+            report
+                .failed()
+                .map(|(entry, _)| &*entry.name)
+                .collect::<Vec<_>>(),
+            ["second"]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn only_buffers_small_inputs() -> Result<()> {
+        /// Records which inputs were replaced ahead of their turn
+        struct Buffered(Vec<bool>);
+
+        impl Sink for Buffered {
+            fn write(&mut self, job: &Job<'_>) -> Result<Status> {
+                self.0.push(job.rendered.is_some());
+                job.write_to(&mut std::io::sink())?;
+                Ok(Status::Written)
+            }
+        }
+
+        let replacer = Replacer::new("a".into(), "b".into(), false, None, 0)?;
+        let big = vec![b'x'; MAX_BUFFERED as usize + 1];
+        let buffered = |threads| -> Result<Vec<bool>> {
+            let inputs: Vec<Box<dyn Input>> = vec![
+                Box::new(Buffer::new("small", b"a".to_vec())),
+                Box::new(Buffer::new("big", big.clone())),
+                Box::new(Reader::new("unknown", &b"a"[..])),
+            ];
+            let options = ProcessOptions {
+                threads,
+                ..Default::default()
+            };
+            let mut sink = Buffered(Vec::new());
+            Processor::new(&replacer, &options)
+                .run(&inputs, &mut sink)?
+                .result()?;
+            Ok(sink.0)
+        };
+
+        assert_eq!(buffered(4)?, [true, false, false]);
+        assert_eq!(buffered(1)?, [false, false, false]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn reports_in_place_changes() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let changed = dir.path().join("changed.txt");
+        let unchanged = dir.path().join("unchanged.txt");
+        fs::write(&changed, "abc")?;
+        fs::write(&unchanged, "xyz")?;
+
+        let replacer = Replacer::new("a".into(), "b".into(), false, None, 0)?;
+        for across in [false, true] {
+            fs::write(&changed, "abc")?;
+            let options = ProcessOptions {
+                across,
+                ..Default::default()
+            };
+            let sources = [
+                Source::File(changed.clone()),
+                Source::File(unchanged.clone()),
+            ];
+            let report = Processor::new(&replacer, &options)
+                .run_parallel(&sources, &InPlace)?;
+
+            let statuses: Vec<_> = report
+                .entries
+                .iter()
+                .map(|entry| entry.result.as_ref().ok().copied())
+                .collect();
+            assert_eq!(
+                statuses,
+                [Some(Status::Modified), Some(Status::Unchanged)]
+            );
+            assert_eq!(fs::read_to_string(&changed)?, "bbc");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn skips_binary_files() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let binary = dir.path().join("lib.so");
+        let text = dir.path().join("notes.txt");
+        fs::write(&binary, b"a\0")?;
+        fs::write(&text, "a")?;
+        let sources =
+            [Source::File(binary.clone()), Source::File(text.clone())];
+
+        let replacer = Replacer::new("a".into(), "b".into(), false, None, 0)?;
+        let options = ProcessOptions {
+            binary: Binary::Skip,
+            ..Default::default()
+        };
+        let report = Processor::new(&replacer, &options)
+            .run_parallel(&sources, &InPlace)?;
+        let skipped: Vec<_> =
+            report.skipped().map(|entry| &entry.path).collect();
+        assert_eq!(skipped, [&Some(binary.clone())]);
+        assert_eq!(report.modified().count(), 1);
+        assert_eq!(fs::read(&binary)?, b"a\0");
+
+        // Nothing is modified when failing
+        fs::write(&text, "a")?;
+        let options = ProcessOptions {
+            binary: Binary::Error,
+            ..Default::default()
+        };
+        let result = Processor::new(&replacer, &options)
+            .run_parallel(&sources, &InPlace);
+        assert!(
+            matches!(result, Err(Error::BinaryFile(path)) if path == binary)
+        );
+        assert_eq!(fs::read_to_string(&text)?, "a");
+
+        Ok(())
+    }
+
+    #[test]
+    fn in_place_needs_files() -> Result<()> {
+        let replacer = Replacer::new("a".into(), "b".into(), false, None, 0)?;
+        let options = ProcessOptions::default();
+        let inputs = [Buffer::new("buffer", b"a".to_vec())];
+
+        let result =
+            Processor::new(&replacer, &options).run_parallel(&inputs, &InPlace);
+        assert!(matches!(result, Err(Error::InvalidPath(_))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn visitor_sees_the_same_matches_in_every_mode() -> Result<()> {
+        use std::sync::{Arc, Mutex};
+
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("file.txt");
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let replacer = crate::ReplacerBuilder::new("b", "c")
+            .visitor({
+                let seen = seen.clone();
+                move |m: &crate::Match<'_>| {
+                    seen.lock().unwrap().push((
+                        m.path.map(Path::to_owned),
+                        m.span.clone(),
+                        m.line,
+                    ));
+                    crate::Decision::Accept
+                }
+            })
+            .build()?;
+
+        for (across, window) in [(false, None), (false, Some(2)), (true, None)]
+        {
+            fs::write(&path, "ab\nb\n\nab")?;
+            let options = ProcessOptions {
+                across,
+                window,
+                ..Default::default()
+            };
+            Processor::new(&replacer, &options)
+                .run_parallel(&[Source::File(path.clone())], &InPlace)?
+                .result()?;
+
+            assert_eq!(fs::read_to_string(&path)?, "ac\nc\n\nac");
+            let seen = std::mem::take(&mut *seen.lock().unwrap());
+            let expected = [(1..2, 1), (3..4, 2), (7..8, 4)]
+                .map(|(span, line)| (Some(path.clone()), span, line));
+            assert_eq!(seen, expected, "across: {across}, window: {window:?}");
+        }
+
+        Ok(())
+    }
+}
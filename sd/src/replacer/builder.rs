@@ -0,0 +1,173 @@
+use std::sync::Arc;
+
+use crate::{Flags, ReplaceTable, Replacer, Result, Visitor};
+
+/// What matches get replaced with
+#[derive(Debug, Clone)]
+enum With {
+    Text(String),
+    Table(ReplaceTable),
+}
+
+/// Configures and builds a [`Replacer`]
+///
+/// ```
+/// use sd::ReplacerBuilder;
+///
+/// let replacer = ReplacerBuilder::new("hello", "bye")
+///     .case_insensitive()
+///     .whole_word()
+///     .build()?;
+/// assert_eq!(&*replacer.replace(b"Hello, othello"), b"bye, othello");
+/// # Ok::<(), sd::Error>(())
+/// ```
+#[derive(Clone)]
+pub struct ReplacerBuilder {
+    look_for: Option<String>,
+    with: With,
+    literal: bool,
+    flags: Flags,
+    limit: usize,
+    strict_map: bool,
+    visitor: Option<Arc<dyn Visitor>>,
+}
+
+impl ReplacerBuilder {
+    /// Replaces matches of the regex `look_for` with `replace_with`, which
+    /// can refer to capture groups like `$1` or `${name}`
+    pub fn new(
+        look_for: impl Into<String>,
+        replace_with: impl Into<String>,
+    ) -> Self {
+        Self {
+            look_for: Some(look_for.into()),
+            with: With::Text(replace_with.into()),
+            literal: false,
+            flags: Flags::default(),
+            limit: 0,
+            strict_map: false,
+            visitor: None,
+        }
+    }
+
+    /// Replaces the keys of `table` with their values
+    ///
+    /// Every key is searched for literally, unless a `pattern` is given.
+    /// Then the text of each of its matches (or just its capture group named
+    /// `key`) gets looked up instead. See [`Replacer::with_table`].
+    pub fn with_table(table: ReplaceTable, pattern: Option<String>) -> Self {
+        Self {
+            look_for: pattern,
+            with: With::Table(table),
+            literal: false,
+            flags: Flags::default(),
+            limit: 0,
+            strict_map: false,
+            visitor: None,
+        }
+    }
+
+    /// Treats the pattern and replacement as plain strings, without any
+    /// regex syntax or capture groups
+    ///
+    /// This does nothing for builders from [`Self::with_table`], whose keys
+    /// and values are plain strings already, and whose pattern is always a
+    /// regex.
+    pub fn literal(mut self) -> Self {
+        self.literal = true;
+        self
+    }
+
+    /// Matches letters regardless of case
+    pub fn case_insensitive(mut self) -> Self {
+        self.flags.case_insensitive = true;
+        self
+    }
+
+    /// Only matches whole words
+    pub fn whole_word(mut self) -> Self {
+        self.flags.whole_word = true;
+        self
+    }
+
+    /// Makes `.` match newlines too
+    ///
+    /// Like the `s` flag, this turns multi-line mode off unless
+    /// [`Self::multi_line`] is set as well.
+    pub fn dot_all(mut self) -> Self {
+        self.flags.dot_all = true;
+        self
+    }
+
+    /// Keeps `^` and `$` matching at the start and end of every line, which
+    /// they do by default unless [`Self::dot_all`] is set
+    pub fn multi_line(mut self) -> Self {
+        self.flags.multi_line = true;
+        self
+    }
+
+    /// Makes `^` and `$` only match at the start and end of the input
+    pub fn single_line(mut self) -> Self {
+        self.flags.single_line = true;
+        self
+    }
+
+    /// Replaces all flags at once, like ones parsed from `--flags`
+    pub fn flags(mut self, flags: Flags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// Stops after `limit` replacements, where 0 means no limit
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    /// Fails on matches that the table has no entry for, instead of leaving
+    /// them untouched, when replacing from a table
+    ///
+    /// Only processing fails; [`Replacer::replace`] stops replacing at such
+    /// a match, and [`Replacer::try_replace`] fails too.
+    pub fn strict_map(mut self) -> Self {
+        self.strict_map = true;
+        self
+    }
+
+    /// Shows every match to `visitor` before it gets replaced, which can
+    /// accept, veto or change the replacement
+    pub fn visitor(mut self, visitor: impl Visitor + 'static) -> Self {
+        self.visitor = Some(Arc::new(visitor));
+        self
+    }
+
+    /// Fails if the pattern isn't a valid regex, or if the flags contradict
+    /// each other, like [`Self::single_line`] and [`Self::multi_line`]
+    pub fn build(self) -> Result<Replacer> {
+        let flags = self.flags.validate()?;
+        let mut replacer = match self.with {
+            With::Text(replace_with) => Replacer::build(
+                self.look_for.unwrap_or_default(),
+                replace_with,
+                self.literal,
+                flags,
+                self.limit,
+            ),
+            // Tables are literal as they are
+            With::Table(table) => Replacer::build_with_table(
+                table,
+                self.look_for,
+                flags,
+                self.limit,
+            ),
+        }?;
+        if self.strict_map {
+            replacer.set_strict();
+        }
+        if let Some(visitor) = self.visitor {
+            replacer.set_visitor(visitor);
+        }
+
+        Ok(replacer)
+    }
+}
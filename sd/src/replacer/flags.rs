@@ -0,0 +1,84 @@
+use std::str::FromStr;
+
+use regex_syntax::{Parser, ParserBuilder};
+
+use crate::Error;
+
+/// Regex flags, as given by the characters of `--flags`
+///
+/// Multi-line mode is on by default, so that `^` and `$` match at the start
+/// and end of every line. The order of the characters doesn't matter, and
+/// flags that contradict each other are rejected instead of one silently
+/// winning.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Flags {
+    /// `i`: match letters regardless of case, as opposed to `c`
+    pub case_insensitive: bool,
+    /// `m`: keep multi-line mode on, even with `s`, as opposed to `e`
+    pub multi_line: bool,
+    /// `e`: turn multi-line mode off
+    pub single_line: bool,
+    /// `s`: make `.` match newlines too, and turn multi-line mode off unless
+    /// `m` is given
+    pub dot_all: bool,
+    /// `w`: only match whole words
+    pub whole_word: bool,
+}
+
+impl Flags {
+    /// Rejects combinations that contradict each other
+    pub(crate) fn validate(self) -> Result<Self, Error> {
+        if self.single_line && self.multi_line {
+            return Err(Error::ConflictingFlags('e', 'm'));
+        }
+
+        Ok(self)
+    }
+
+    /// Whether `^` and `$` match at the start and end of every line
+    pub(crate) fn is_multi_line(&self) -> bool {
+        !self.single_line && (self.multi_line || !self.dot_all)
+    }
+
+    /// Whether these flags leave literal matching untouched, so that a plain
+    /// substring search gives the same results as the regex engine
+    pub(crate) fn is_literal_compatible(&self) -> bool {
+        !self.case_insensitive && !self.whole_word
+    }
+
+    /// Parses patterns the way the regex engine does with these flags, for
+    /// looking into what they match
+    pub(crate) fn parser(&self) -> Parser {
+        ParserBuilder::new()
+            .case_insensitive(self.case_insensitive)
+            .multi_line(self.is_multi_line())
+            .dot_matches_new_line(self.dot_all)
+            .utf8(false)
+            .build()
+    }
+}
+
+impl FromStr for Flags {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut flags = Self::default();
+        let mut case_sensitive = false;
+        for c in s.chars() {
+            match c {
+                'c' => case_sensitive = true,
+                'i' => flags.case_insensitive = true,
+                'm' => flags.multi_line = true,
+                'e' => flags.single_line = true,
+                's' => flags.dot_all = true,
+                'w' => flags.whole_word = true,
+                _ => return Err(Error::UnknownFlag(c)),
+            }
+        }
+
+        if case_sensitive && flags.case_insensitive {
+            return Err(Error::ConflictingFlags('c', 'i'));
+        }
+        flags.validate()
+    }
+}
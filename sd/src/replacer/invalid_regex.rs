@@ -0,0 +1,146 @@
+use std::{error::Error, fmt, ops::Range};
+
+use regex_syntax::ast::ErrorKind;
+
+use super::{Flags, validate::underline};
+
+/// A pattern to look for that isn't a valid regex
+#[derive(Debug)]
+pub struct InvalidRegex {
+    pattern: String,
+    message: String,
+    hint: Option<String>,
+    span: Option<Range<usize>>,
+    meant_literally: Option<char>,
+}
+
+impl Error for InvalidRegex {}
+
+impl InvalidRegex {
+    /// Explains why the regex built from `pattern` failed with `error`
+    pub(crate) fn new(
+        pattern: &str,
+        flags: Flags,
+        error: regex::Error,
+    ) -> Self {
+        // The regex crate only reports syntax errors as text, so the pattern
+        // is parsed again to find out where the error is. That's done without
+        // the wrapping of `-f w`, which could move the error elsewhere.
+        let parsed = flags.parser().parse(pattern);
+        let mut meant_literally = None;
+        let (message, hint, span) = match parsed {
+            Err(regex_syntax::Error::Parse(e)) => {
+                let span = e.span().start.offset..e.span().end.offset;
+                let at = &pattern[span.start..];
+                meant_literally = match e.kind() {
+                    ErrorKind::GroupUnclosed
+                    | ErrorKind::GroupUnopened
+                    | ErrorKind::ClassUnclosed
+                    | ErrorKind::RepetitionMissing => at.chars().next(),
+                    _ => None,
+                };
+                let hint = match meant_literally {
+                    Some(c) => Some(format!(
+                        "To search for a literal `{c}`, escape it like \
+                         `\\{c}`, or search for the whole pattern literally."
+                    )),
+                    None => hint(e.kind(), at),
+                };
+                (e.kind().to_string(), hint, Some(span))
+            }
+            Err(regex_syntax::Error::Translate(e)) => {
+                let span = e.span().start.offset..e.span().end.offset;
+                (e.kind().to_string(), None, Some(span))
+            }
+            _ => (error.to_string(), None, None),
+        };
+
+        Self {
+            pattern: pattern.to_owned(),
+            message,
+            hint,
+            span,
+            meant_literally,
+        }
+    }
+
+    /// The pattern as it was given
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    /// What's wrong with the pattern, without pointing out where
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// How the pattern might be fixed, for common mistakes
+    pub fn hint(&self) -> Option<&str> {
+        self.hint.as_deref()
+    }
+
+    /// Replaces the hint, for callers that can word it better, like in terms
+    /// of their own options
+    pub fn with_hint(mut self, hint: impl Into<String>) -> Self {
+        self.hint = Some(hint.into());
+        self
+    }
+
+    /// A special character that was likely meant to be searched for
+    /// literally, when that's what the error is about
+    pub fn meant_literally(&self) -> Option<char> {
+        self.meant_literally
+    }
+
+    /// Byte offsets of the part of [`Self::pattern`] that's at fault, when
+    /// the error is about one part of it
+    pub fn span(&self) -> Option<Range<usize>> {
+        self.span.clone()
+    }
+}
+
+impl fmt::Display for InvalidRegex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid regex: {}", self.message)?;
+        if let Some(hint) = &self.hint {
+            write!(f, "\nhint: {hint}")?;
+        }
+        if let Some(span) = self.span() {
+            write!(f, "\n{}", underline(&self.pattern, span))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Suggests a fix for errors that mostly come from mistaking the pattern for
+/// another flavor of regex. `at` is the pattern from where the error is on.
+fn hint(kind: &ErrorKind, at: &str) -> Option<String> {
+    let hint = match kind {
+        ErrorKind::UnsupportedLookAround if at.starts_with("(?<=") => {
+            "Capture the text before the match instead and put it back, \
+             like replacing `(a)b` with `${1}c` rather than `(?<=a)b` with \
+             `c`."
+                .to_owned()
+        }
+        ErrorKind::UnsupportedLookAround if at.starts_with("(?=") => {
+            "Capture the text after the match instead and put it back, \
+             like replacing `a(b)` with `c${1}` rather than `a(?=b)` with \
+             `c`."
+                .to_owned()
+        }
+        ErrorKind::UnsupportedLookAround => {
+            "A negated class like `[^b]` can often stand in for negative \
+             look-around, with the text it matches captured and put back \
+             like `${1}`."
+                .to_owned()
+        }
+        ErrorKind::UnsupportedBackreference => {
+            "To use a group in the replacement, refer to it like `$1` there."
+                .to_owned()
+        }
+        _ => return None,
+    };
+
+    Some(hint)
+}
@@ -0,0 +1,225 @@
+use std::{fmt, ops::Range};
+
+use regex::bytes::Regex;
+use regex_syntax::hir::{Class, Hir, HirKind, Look};
+use serde_json::{Value, json};
+
+use super::{Flags, validate::capture_refs};
+use crate::Argument;
+
+/// A likely mistake in the arguments of a [`crate::Replacer`], which makes
+/// them quietly do less than they seem to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Warning {
+    /// The replacement refers to a numbered group that the pattern doesn't
+    /// have, which is replaced with nothing
+    MissingGroup {
+        reference: String,
+        groups: usize,
+        span: Range<usize>,
+    },
+    /// The replacement refers to a named group that the pattern doesn't
+    /// have, which is replaced with nothing
+    MissingName {
+        reference: String,
+        span: Range<usize>,
+    },
+    /// Part of the pattern looks for a newline, which is never there when
+    /// replacing line by line
+    Newline,
+    /// The pattern has `^` or `$` while `-f e` makes them match only at the
+    /// start and end of the whole input
+    SingleLineAnchor,
+}
+
+impl Warning {
+    /// Identifies the kind of warning, like [`crate::Error::code`]
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::MissingGroup { .. } => "missing-group",
+            Self::MissingName { .. } => "missing-name",
+            Self::Newline => "newline-in-line-mode",
+            Self::SingleLineAnchor => "single-line-anchor",
+        }
+    }
+
+    /// The argument that the warning is about
+    pub fn argument(&self) -> Argument {
+        match self {
+            Self::MissingGroup { .. } | Self::MissingName { .. } => {
+                Argument::ReplaceWith
+            }
+            Self::Newline | Self::SingleLineAnchor => Argument::Find,
+        }
+    }
+
+    /// Whether the mistake matters when processing with `options`, since
+    /// some patterns only go wrong in line mode or only outside of it
+    #[cfg(feature = "io")]
+    pub fn applies_to(&self, options: &crate::ProcessOptions) -> bool {
+        match self {
+            Self::MissingGroup { .. } | Self::MissingName { .. } => true,
+            Self::Newline => options.is_line_by_line(),
+            // Every record is an input of its own
+            Self::SingleLineAnchor => !options.is_by_record(),
+        }
+    }
+
+    /// Renders the warning like [`crate::Error::to_json`]
+    pub fn to_json(&self) -> Value {
+        let mut json = json!({
+            "code": self.code(),
+            "message": self.to_string(),
+            "argument": self.argument().name(),
+        });
+        if let Self::MissingGroup { span, .. }
+        | Self::MissingName { span, .. } = self
+        {
+            json["span"] = json!({ "start": span.start, "end": span.end });
+        }
+
+        json
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingGroup {
+                reference, groups, ..
+            } => write!(
+                f,
+                "the replacement refers to `{reference}`, but the pattern \
+                 only has {groups} capture group{}",
+                if *groups == 1 { "" } else { "s" }
+            ),
+            Self::MissingName { reference, .. } => write!(
+                f,
+                "the replacement refers to `{reference}`, but the pattern has \
+                 no capture group by that name"
+            ),
+            Self::Newline => f.write_str(
+                "the pattern looks for a newline in places, which lines never \
+                 contain when replacing line by line (use `-A` to replace \
+                 across lines)",
+            ),
+            Self::SingleLineAnchor => f.write_str(
+                "with `-f e`, `^` and `$` only match at the start and end of \
+                 the whole input",
+            ),
+        }
+    }
+}
+
+/// What's found out about a pattern before matching with it
+#[derive(Debug, Default)]
+pub(crate) struct PatternLints {
+    pub(crate) warnings: Vec<Warning>,
+    /// Whether every match has a newline in it, so that nothing can match
+    /// when lines are replaced one at a time
+    pub(crate) needs_newline: bool,
+}
+
+/// Looks for mistakes in a `look_for` regex
+pub(crate) fn lint_pattern(look_for: &str, flags: Flags) -> PatternLints {
+    let Ok(hir) = flags.parser().parse(look_for) else {
+        return PatternLints::default();
+    };
+
+    let needs_newline = needs_newline(&hir);
+    let mut warnings = Vec::new();
+    // Patterns that can't match at all fail instead, see `needs_newline`
+    if !needs_newline
+        && any(&hir, &|hir| match hir.kind() {
+            HirKind::Literal(literal) => literal.0.contains(&b'\n'),
+            HirKind::Class(class) => names_newline(class),
+            _ => false,
+        })
+    {
+        warnings.push(Warning::Newline);
+    }
+    if flags.single_line
+        && any(&hir, &|hir| {
+            matches!(hir.kind(), HirKind::Look(Look::Start | Look::End))
+        })
+    {
+        warnings.push(Warning::SingleLineAnchor);
+    }
+
+    PatternLints {
+        warnings,
+        needs_newline,
+    }
+}
+
+/// Looks for references in `replace_with` to groups that `regex` doesn't have
+pub(crate) fn lint_replacement(
+    replace_with: &str,
+    regex: &Regex,
+) -> Vec<Warning> {
+    let groups = regex.captures_len() - 1;
+    capture_refs(replace_with)
+        .filter_map(|(name, span)| {
+            let reference = replace_with[span.clone()].to_owned();
+            match name.parse::<usize>() {
+                Ok(index) if index > groups => Some(Warning::MissingGroup {
+                    reference,
+                    groups,
+                    span,
+                }),
+                Ok(_) => None,
+                Err(_)
+                    if regex.capture_names().flatten().any(|n| n == name) =>
+                {
+                    None
+                }
+                Err(_) => Some(Warning::MissingName { reference, span }),
+            }
+        })
+        .collect()
+}
+
+/// Whether `f` holds for `hir` or any expression nested in it
+fn any(hir: &Hir, f: &impl Fn(&Hir) -> bool) -> bool {
+    f(hir)
+        || match hir.kind() {
+            HirKind::Capture(capture) => any(&capture.sub, f),
+            HirKind::Repetition(repetition) => any(&repetition.sub, f),
+            HirKind::Concat(hirs) | HirKind::Alternation(hirs) => {
+                hirs.iter().any(|hir| any(hir, f))
+            }
+            _ => false,
+        }
+}
+
+/// Whether `class` has a newline on its own, like `[\n,]` or the `a|\n` that
+/// alternations of single characters become, rather than in a range like
+/// `\s` does
+fn names_newline(class: &Class) -> bool {
+    match class {
+        Class::Unicode(class) => class
+            .ranges()
+            .iter()
+            .any(|range| range.start() == '\n' && range.end() == '\n'),
+        Class::Bytes(class) => class
+            .ranges()
+            .iter()
+            .any(|range| range.start() == b'\n' && range.end() == b'\n'),
+    }
+}
+
+/// Whether every match of `hir` has a newline in it, either literally or from
+/// a class of nothing but newlines
+fn needs_newline(hir: &Hir) -> bool {
+    match hir.kind() {
+        HirKind::Literal(literal) => literal.0.contains(&b'\n'),
+        HirKind::Class(class) => class.literal().is_some_and(|c| c == b"\n"),
+        HirKind::Capture(capture) => needs_newline(&capture.sub),
+        HirKind::Repetition(repetition) => {
+            repetition.min > 0 && needs_newline(&repetition.sub)
+        }
+        HirKind::Concat(hirs) => hirs.iter().any(needs_newline),
+        HirKind::Alternation(hirs) => hirs.iter().all(needs_newline),
+        HirKind::Empty | HirKind::Look(_) => false,
+    }
+}
@@ -1,23 +1,165 @@
-use std::borrow::Cow;
+use std::{borrow::Cow, ops::Range, path::Path, sync::Arc};
 
 use crate::{Result, unescape};
 
-use regex::bytes::Regex;
+use aho_corasick::{AhoCorasick, MatchKind};
+use memchr::memmem;
+use regex::bytes::{Regex, Replacer as _};
 
+mod builder;
+mod flags;
+mod invalid_regex;
+mod lint;
+mod table;
 #[cfg(test)]
 mod tests;
 mod validate;
+mod visit;
 
+pub use builder::ReplacerBuilder;
+pub use flags::Flags;
+pub use invalid_regex::InvalidRegex;
+pub use lint::Warning;
+pub use table::ReplaceTable;
+use table::TableReplacer;
 pub use validate::{InvalidReplaceCapture, validate_replace};
+pub use visit::{Decision, Match, Visitor};
+
+/// The engine used to find matches
+///
+/// Literal patterns skip the regex engine entirely when no flag needs it
+enum Matcher {
+    Regex(Regex),
+    Literal(Box<memmem::Finder<'static>>),
+    Literals(AhoCorasick),
+}
+
+impl Matcher {
+    /// Finds the leftmost match that starts at or after `at`
+    ///
+    /// Unlike slicing `haystack`, the bytes before `at` are still visible to
+    /// look-around assertions like `^` and `\b`.
+    fn find_at(&self, haystack: &[u8], at: usize) -> Option<Range<usize>> {
+        match self {
+            Self::Regex(regex) => {
+                regex.find_at(haystack, at).map(|m| m.range())
+            }
+            Self::Literal(finder) => finder
+                .find(&haystack[at..])
+                .map(|start| at + start..at + start + finder.needle().len()),
+            Self::Literals(ac) => ac
+                .find(
+                    aho_corasick::Input::new(haystack).span(at..haystack.len()),
+                )
+                .map(|m| m.range()),
+        }
+    }
+}
+
+/// Progress of a replacement that is carried over between calls to
+/// [`Replacer::replace_range`]
+#[derive(Debug, Default)]
+pub(crate) struct ReplaceState<'a> {
+    /// How many replacements have been made so far
+    pub(crate) count: usize,
+    /// Where the last match ended, which is needed to skip empty matches
+    /// right after it
+    pub(crate) last_match_end: Option<usize>,
+    /// The file being replaced in, as told to a [`Visitor`]
+    pub(crate) path: Option<&'a Path>,
+    /// Where the start of the haystack is in the whole input
+    pub(crate) offset: usize,
+    /// How many newlines there are in the whole input before `counted`
+    pub(crate) newlines: usize,
+    /// How far into the haystack newlines have been counted
+    counted: usize,
+    /// A match that a strict table has no entry for, which stopped the
+    /// replacement
+    unmapped: Option<Vec<u8>>,
+}
+
+impl<'a> ReplaceState<'a> {
+    /// Starts at `offset` into the input at `path`, after `newlines` lines
+    #[cfg(feature = "io")]
+    pub(crate) fn at(
+        path: Option<&'a Path>,
+        offset: usize,
+        newlines: usize,
+    ) -> Self {
+        Self {
+            path,
+            offset,
+            newlines,
+            ..Default::default()
+        }
+    }
+
+    /// Adjusts for the `dropped` bytes being removed from the front of the
+    /// haystack
+    #[cfg(feature = "io")]
+    pub(crate) fn shift(&mut self, dropped: &[u8]) {
+        let n = dropped.len();
+        self.last_match_end =
+            self.last_match_end.and_then(|end| end.checked_sub(n));
+        if let Some(rest) = dropped.get(self.counted..) {
+            self.newlines += memchr::memchr_iter(b'\n', rest).count();
+        }
+        self.counted = self.counted.saturating_sub(n);
+        self.offset += n;
+    }
+
+    /// Fails if the replacement stopped at a match that isn't in a strict
+    /// table
+    pub(crate) fn check(&mut self) -> Result<()> {
+        match self.unmapped.take() {
+            Some(key) => Err(crate::Error::UnmappedMatch(
+                String::from_utf8_lossy(&key).into_owned(),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// The line that `pos` in `haystack` is on, counting from 1
+    fn line_at(&mut self, haystack: &[u8], pos: usize) -> usize {
+        if let Some(skipped) = haystack.get(self.counted..pos) {
+            self.newlines += memchr::memchr_iter(b'\n', skipped).count();
+            self.counted = pos;
+        }
+        self.newlines + 1
+    }
+}
+
+enum Replacement {
+    /// Inserted as-is, either from string mode or from a template that
+    /// doesn't reference any capture groups
+    Fixed(Vec<u8>),
+    Template(Vec<u8>),
+    Table {
+        table: ReplaceTable,
+        /// The table from [`ReplaceTable::folded`], when matching ignores
+        /// case
+        folded: Option<ReplaceTable>,
+        key_group: Option<usize>,
+        /// Fail on matches that aren't in the table, instead of leaving them
+        strict: bool,
+    },
+}
 
 pub struct Replacer {
-    regex: Regex,
-    replace_with: Vec<u8>,
-    is_literal: bool,
+    matcher: Matcher,
+    replacement: Replacement,
     replacements: usize,
+    visitor: Option<Arc<dyn Visitor>>,
+    warnings: Vec<Warning>,
+    /// Whether every match has a newline in it
+    needs_newline: bool,
 }
 
 impl Replacer {
+    /// Creates a replacer from the raw arguments of the command line,
+    /// failing on any character in `flags` that isn't a known flag
+    ///
+    /// [`ReplacerBuilder`] is usually more convenient.
     pub fn new(
         look_for: String,
         replace_with: String,
@@ -25,99 +167,357 @@ impl Replacer {
         flags: Option<String>,
         replacements: usize,
     ) -> Result<Self> {
-        let (look_for, replace_with) = if is_literal {
-            (regex::escape(&look_for), replace_with.into_bytes())
-        } else {
-            validate_replace(&replace_with)?;
+        let flags = flags.as_deref().unwrap_or_default().parse()?;
+        Self::build(look_for, replace_with, is_literal, flags, replacements)
+    }
 
-            (look_for, unescape::unescape(&replace_with).into_bytes())
-        };
+    /// Creates a replacer that looks up matches in `table` and replaces them
+    /// with the corresponding value
+    ///
+    /// Without a `look_for` pattern, every key in the table is searched for
+    /// literally. With one, the text of each match is looked up instead, or
+    /// just its capture group named `key` if there is one. With the `i`
+    /// flag, keys that only match ignoring case are found too. Matches
+    /// missing from the table are left untouched, unless
+    /// [`ReplacerBuilder::strict_map`] is used.
+    pub fn with_table(
+        table: ReplaceTable,
+        look_for: Option<String>,
+        flags: Option<String>,
+        replacements: usize,
+    ) -> Result<Self> {
+        let flags = flags.as_deref().unwrap_or_default().parse()?;
+        Self::build_with_table(table, look_for, flags, replacements)
+    }
 
-        let mut regex = regex::bytes::RegexBuilder::new(&look_for);
-        regex.multi_line(true);
-
-        if let Some(flags) = flags {
-            flags.chars().for_each(|c| {
-                #[rustfmt::skip]
-                match c {
-                    'c' => { regex.case_insensitive(false); },
-                    'i' => { regex.case_insensitive(true); },
-                    'm' => {},
-                    'e' => { regex.multi_line(false); },
-                    's' => {
-                        if !flags.contains('m') {
-                            regex.multi_line(false);
-                        }
-                        regex.dot_matches_new_line(true);
-                    },
-                    'w' => {
-                        regex = regex::bytes::RegexBuilder::new(&format!(
-                            "\\b{}\\b",
-                            look_for
-                        ));
-                    },
-                    _ => {},
+    pub(crate) fn build(
+        look_for: String,
+        replace_with: String,
+        is_literal: bool,
+        flags: Flags,
+        replacements: usize,
+    ) -> Result<Self> {
+        if is_literal {
+            let lints = lint::lint_pattern(&regex::escape(&look_for), flags);
+            let matcher =
+                if !look_for.is_empty() && flags.is_literal_compatible() {
+                    Matcher::Literal(Box::new(
+                        memmem::Finder::new(look_for.as_bytes()).into_owned(),
+                    ))
+                } else {
+                    Matcher::Regex(Self::build_regex(
+                        &regex::escape(&look_for),
+                        flags,
+                    )?)
                 };
+
+            return Ok(Self {
+                matcher,
+                replacement: Replacement::Fixed(replace_with.into_bytes()),
+                replacements,
+                visitor: None,
+                warnings: lints.warnings,
+                needs_newline: lints.needs_newline,
             });
+        }
+
+        validate_replace(&replace_with)?;
+        let regex = Self::build_regex(&look_for, flags)?;
+        let mut lints = lint::lint_pattern(&look_for, flags);
+        lints
+            .warnings
+            .extend(lint::lint_replacement(&replace_with, &regex));
+        let replace_with = unescape::unescape(&replace_with);
+        let replacement = if validate::has_captures(&replace_with) {
+            Replacement::Template(replace_with.into_bytes())
+        } else {
+            // Only escaped `$$`s are left to expand
+            Replacement::Fixed(replace_with.replace("$$", "$").into_bytes())
         };
 
         Ok(Self {
-            regex: regex.build()?,
-            replace_with,
-            is_literal,
+            matcher: Matcher::Regex(regex),
+            replacement,
             replacements,
+            visitor: None,
+            warnings: lints.warnings,
+            needs_newline: lints.needs_newline,
         })
     }
 
-    pub fn replace<'a>(&'a self, content: &'a [u8]) -> Cow<'a, [u8]> {
-        let regex = &self.regex;
-        let limit = self.replacements;
-        let use_color = false;
-        if self.is_literal {
-            Self::replacen(
-                regex,
-                limit,
-                content,
-                use_color,
-                regex::bytes::NoExpand(&self.replace_with),
-            )
+    pub(crate) fn build_with_table(
+        table: ReplaceTable,
+        look_for: Option<String>,
+        flags: Flags,
+        replacements: usize,
+    ) -> Result<Self> {
+        table.validate()?;
+        let lints = match &look_for {
+            Some(look_for) => lint::lint_pattern(look_for, flags),
+            None => {
+                let has_newline = |key: &[u8]| key.contains(&b'\n');
+                let needs_newline = table.keys().all(has_newline);
+                let warnings =
+                    if !needs_newline && table.keys().any(has_newline) {
+                        vec![Warning::Newline]
+                    } else {
+                        Vec::new()
+                    };
+                lint::PatternLints {
+                    warnings,
+                    needs_newline,
+                }
+            }
+        };
+        // Tables too big for the automaton fall back to the regex engine,
+        // which fails with an error of its own if they're too big for it too
+        let literals = match look_for {
+            None if flags.is_literal_compatible() => AhoCorasick::builder()
+                .match_kind(MatchKind::LeftmostLongest)
+                .build(table.keys())
+                .ok(),
+            _ => None,
+        };
+        let (matcher, key_group) = match literals {
+            Some(ac) => (Matcher::Literals(ac), None),
+            None => {
+                let look_for = look_for.unwrap_or_else(|| table.pattern());
+                let regex = Self::build_regex(&look_for, flags)?;
+                let key_group =
+                    regex.capture_names().position(|name| name == Some("key"));
+                (Matcher::Regex(regex), key_group)
+            }
+        };
+
+        Ok(Self {
+            matcher,
+            replacement: Replacement::Table {
+                folded: flags.case_insensitive.then(|| table.folded()),
+                table,
+                key_group,
+                strict: false,
+            },
+            replacements,
+            visitor: None,
+            warnings: lints.warnings,
+            needs_newline: lints.needs_newline,
+        })
+    }
+
+    fn build_regex(look_for: &str, flags: Flags) -> Result<Regex> {
+        let compiled = if flags.whole_word {
+            Cow::Owned(format!(r"\b(?:{look_for})\b"))
         } else {
-            Self::replacen(
-                regex,
-                limit,
-                content,
-                use_color,
-                &*self.replace_with,
-            )
+            Cow::Borrowed(look_for)
+        };
+
+        regex::bytes::RegexBuilder::new(&compiled)
+            .case_insensitive(flags.case_insensitive)
+            .multi_line(flags.is_multi_line())
+            .dot_matches_new_line(flags.dot_all)
+            .build()
+            .map_err(|e| InvalidRegex::new(look_for, flags, e).into())
+    }
+
+    /// Likely mistakes in the arguments the replacer was built from
+    ///
+    /// Some only matter in some modes, see [`Warning::applies_to`].
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    /// Whether every match has a newline in it, so that nothing matches
+    /// when lines are replaced one at a time
+    pub fn needs_newline(&self) -> bool {
+        self.needs_newline
+    }
+
+    /// Fails when nothing can match with `options`, see
+    /// [`Self::needs_newline`]
+    #[cfg(feature = "io")]
+    pub(crate) fn check_mode(
+        &self,
+        options: &crate::ProcessOptions,
+    ) -> Result<()> {
+        if self.needs_newline() && options.is_line_by_line() {
+            return Err(crate::Error::NeedsNewline);
+        }
+
+        Ok(())
+    }
+
+    /// Fails on matches that a table has no entry for, instead of leaving
+    /// them untouched
+    pub(crate) fn set_strict(&mut self) {
+        if let Replacement::Table { strict, .. } = &mut self.replacement {
+            *strict = true;
         }
     }
 
-    /// A modified form of [`regex::bytes::Regex::replacen`] that supports
-    /// coloring replacements
-    pub fn replacen<'haystack, R: regex::bytes::Replacer>(
-        regex: &regex::bytes::Regex,
-        limit: usize,
-        haystack: &'haystack [u8],
-        _use_color: bool,
-        mut rep: R,
-    ) -> Cow<'haystack, [u8]> {
-        let mut it = regex.captures_iter(haystack).enumerate().peekable();
-        if it.peek().is_none() {
-            return Cow::Borrowed(haystack);
+    /// Has every match shown to `visitor` before it gets replaced
+    pub(crate) fn set_visitor(&mut self, visitor: Arc<dyn Visitor>) {
+        self.visitor = Some(visitor);
+    }
+
+    #[cfg(feature = "io")]
+    pub(crate) fn has_visitor(&self) -> bool {
+        self.visitor.is_some()
+    }
+
+    pub fn replace<'a>(&self, content: &'a [u8]) -> Cow<'a, [u8]> {
+        self.replace_in(content, &mut ReplaceState::default())
+    }
+
+    /// Like [`Self::replace`], but fails on a match that a strict table has
+    /// no entry for, where [`Self::replace`] stops replacing at it
+    pub fn try_replace<'a>(&self, content: &'a [u8]) -> Result<Cow<'a, [u8]>> {
+        let mut state = ReplaceState::default();
+        let replaced = self.replace_in(content, &mut state);
+        state.check()?;
+        Ok(replaced)
+    }
+
+    /// Like [`Self::replace`], but starting from `state`
+    pub(crate) fn replace_in<'a>(
+        &self,
+        content: &'a [u8],
+        state: &mut ReplaceState<'_>,
+    ) -> Cow<'a, [u8]> {
+        let mut new = Vec::new();
+        let end =
+            self.replace_range(content, 0, content.len(), state, &mut new);
+        if state.count == 0 {
+            return Cow::Borrowed(content);
         }
-        let mut new = Vec::with_capacity(haystack.len());
-        let mut last_match = 0;
-        for (i, cap) in it {
-            // unwrap on 0 is OK because captures only reports matches
-            let m = cap.get(0).unwrap();
-            new.extend_from_slice(&haystack[last_match..m.start()]);
-            rep.replace_append(&cap, &mut new);
-            last_match = m.end();
-            if limit > 0 && i >= limit - 1 {
+        new.extend_from_slice(&content[end..]);
+        Cow::Owned(new)
+    }
+
+    /// Replaces the matches in `haystack` that start within `start..=stop`
+    ///
+    /// The text from `start` up to the end of the last replaced match is
+    /// appended to `dst`, with the returned offset marking where that text
+    /// ends. Anything after it is left for the caller.
+    pub(crate) fn replace_range(
+        &self,
+        haystack: &[u8],
+        start: usize,
+        stop: usize,
+        state: &mut ReplaceState<'_>,
+        dst: &mut Vec<u8>,
+    ) -> usize {
+        let limit = self.replacements;
+        let mut last_match = start;
+        let mut at = start;
+
+        while (limit == 0 || state.count < limit) && at <= haystack.len() {
+            let needs_captures = self.visitor.is_some()
+                || matches!(
+                    self.replacement,
+                    Replacement::Template(_)
+                        | Replacement::Table {
+                            key_group: Some(_),
+                            ..
+                        }
+                );
+            let (span, caps) = match &self.matcher {
+                Matcher::Regex(regex) if needs_captures => match regex
+                    .captures_at(haystack, at)
+                {
+                    // unwrap on 0 is OK because captures only reports matches
+                    Some(caps) => (caps.get(0).unwrap().range(), Some(caps)),
+                    None => break,
+                },
+                matcher => match matcher.find_at(haystack, at) {
+                    Some(span) => (span, None),
+                    None => break,
+                },
+            };
+
+            // Mirror the regex crate's iterators: an empty match right where
+            // the previous one ended is skipped
+            if span.is_empty() && state.last_match_end == Some(span.end) {
+                at = span.end + 1;
+                continue;
+            }
+            if span.start > stop {
                 break;
             }
+
+            if state.count == 0 && dst.capacity() == 0 {
+                dst.reserve(haystack.len() - start);
+            }
+            dst.extend_from_slice(&haystack[last_match..span.start]);
+            let mark = dst.len();
+            match (&self.replacement, &caps) {
+                (Replacement::Fixed(replace_with), _) => {
+                    dst.extend_from_slice(replace_with)
+                }
+                (Replacement::Template(replace_with), Some(caps)) => {
+                    caps.expand(replace_with, dst)
+                }
+                (
+                    Replacement::Table {
+                        table,
+                        folded,
+                        key_group,
+                        strict,
+                    },
+                    caps,
+                ) => {
+                    let mut replacer = TableReplacer {
+                        table,
+                        folded: folded.as_ref(),
+                        key_group: *key_group,
+                        missing: None,
+                    };
+                    match caps {
+                        Some(caps) => replacer.replace_append(caps, dst),
+                        None => {
+                            replacer.replace_key(&haystack[span.clone()], dst)
+                        }
+                    }
+                    if let (true, Some(key)) = (*strict, replacer.missing) {
+                        // Leave the rest for the caller to fail on
+                        dst.truncate(mark);
+                        state.unmapped = Some(key);
+                        last_match = span.start;
+                        break;
+                    }
+                }
+                (Replacement::Template(_), None) => {
+                    unreachable!("templates are always expanded from captures")
+                }
+            }
+
+            let decision = self.visitor.as_ref().map(|visitor| {
+                visitor.visit(&Match {
+                    path: state.path,
+                    span: state.offset + span.start..state.offset + span.end,
+                    line: state.line_at(haystack, span.start),
+                    text: &haystack[span.clone()],
+                    captures: caps.as_ref(),
+                    replacement: &dst[mark..],
+                })
+            });
+            match decision {
+                None | Some(Decision::Accept) => state.count += 1,
+                Some(Decision::Veto) => {
+                    dst.truncate(mark);
+                    dst.extend_from_slice(&haystack[span.clone()]);
+                }
+                Some(Decision::Replace(replace_with)) => {
+                    dst.truncate(mark);
+                    dst.extend_from_slice(&replace_with);
+                    state.count += 1;
+                }
+            }
+
+            last_match = span.end;
+            at = span.end;
+            state.last_match_end = Some(span.end);
         }
-        new.extend_from_slice(&haystack[last_match..]);
-        Cow::Owned(new)
+
+        last_match
     }
 }
@@ -0,0 +1,342 @@
+use std::collections::HashMap;
+
+use regex_syntax::hir::{ClassUnicode, ClassUnicodeRange};
+#[cfg(feature = "io")]
+use std::{fs, path::Path};
+
+use crate::{Error, Result};
+
+/// A key → value lookup table used to replace many different matches in a
+/// single pass
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ReplaceTable(HashMap<Vec<u8>, Vec<u8>>);
+
+impl ReplaceTable {
+    /// Loads a table from `path`, picking the format from the extension
+    ///
+    /// `.json` files must contain a single object of string values, `.csv`
+    /// files have two columns per record, and anything else is read as
+    /// tab-separated `key<TAB>value` lines.
+    #[cfg(feature = "io")]
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(str::to_ascii_lowercase);
+        let parsed = match extension.as_deref() {
+            Some("json") => Self::parse_json(&contents),
+            Some("csv") => Self::parse_csv(&contents),
+            _ => Self::parse_tsv(&contents),
+        };
+
+        parsed
+            .map_err(|reason| Error::InvalidMap(Some(path.to_owned()), reason))
+    }
+
+    pub fn parse_tsv(s: &str) -> Result<Self, String> {
+        let mut table = Self::default();
+        for (i, line) in s.lines().enumerate() {
+            if line.is_empty() {
+                continue;
+            }
+            let Some((key, value)) = line.split_once('\t') else {
+                return Err(format!("line {}: missing tab separator", i + 1));
+            };
+            table.insert(key, value);
+        }
+
+        Ok(table)
+    }
+
+    pub fn parse_csv(s: &str) -> Result<Self, String> {
+        let mut table = Self::default();
+        let mut chars = s.chars().peekable();
+        let mut record = Vec::new();
+        let mut field = String::new();
+        let mut line = 1;
+
+        loop {
+            let c = chars.next();
+            match c {
+                Some('"') if field.is_empty() => loop {
+                    match chars.next() {
+                        Some('"') if chars.peek() == Some(&'"') => {
+                            chars.next();
+                            field.push('"');
+                        }
+                        Some('"') => break,
+                        Some(c) => {
+                            if c == '\n' {
+                                line += 1;
+                            }
+                            field.push(c);
+                        }
+                        None => {
+                            return Err(format!(
+                                "line {line}: unterminated quoted field"
+                            ));
+                        }
+                    }
+                },
+                Some(',') => record.push(std::mem::take(&mut field)),
+                Some('\r') if chars.peek() == Some(&'\n') => {}
+                Some('\n') | None => {
+                    if !field.is_empty() || !record.is_empty() {
+                        record.push(std::mem::take(&mut field));
+                        let [key, value] = <[String; 2]>::try_from(
+                            std::mem::take(&mut record),
+                        )
+                        .map_err(|record| {
+                            format!(
+                                "line {line}: expected 2 fields, found {}",
+                                record.len()
+                            )
+                        })?;
+                        table.insert(&key, &value);
+                    }
+                    if c.is_none() {
+                        break;
+                    }
+                    line += 1;
+                }
+                Some(c) => field.push(c),
+            }
+        }
+
+        Ok(table)
+    }
+
+    pub fn parse_json(s: &str) -> Result<Self, String> {
+        let map: HashMap<String, String> =
+            serde_json::from_str(s).map_err(|e| e.to_string())?;
+        Ok(Self(
+            map.into_iter()
+                .map(|(k, v)| (k.into_bytes(), v.into_bytes()))
+                .collect(),
+        ))
+    }
+
+    pub fn insert(&mut self, key: &str, value: &str) {
+        self.0.insert(key.into(), value.into());
+    }
+
+    pub fn get(&self, key: &[u8]) -> Option<&[u8]> {
+        self.0.get(key).map(Vec::as_slice)
+    }
+
+    pub(crate) fn keys(&self) -> impl Iterator<Item = &[u8]> {
+        self.0.keys().map(Vec::as_slice)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Fails on tables that would match everywhere or nowhere
+    pub(crate) fn validate(&self) -> Result<()> {
+        let reason = if self.is_empty() {
+            "no entries"
+        } else if self.get(b"").is_some() {
+            "empty key"
+        } else {
+            return Ok(());
+        };
+
+        Err(Error::InvalidMap(None, reason.into()))
+    }
+
+    /// The same table with case folded keys, for looking up matches
+    /// regardless of case
+    ///
+    /// Of keys that only differ in case, the first in byte order wins.
+    pub(crate) fn folded(&self) -> Self {
+        let mut keys: Vec<_> = self.0.keys().collect();
+        keys.sort();
+        let mut folded = HashMap::with_capacity(keys.len());
+        for key in keys {
+            if let Some(key_folded) = fold_case(key) {
+                folded
+                    .entry(key_folded)
+                    .or_insert_with(|| self.0[key].clone());
+            }
+        }
+
+        Self(folded)
+    }
+
+    /// Builds a regex that matches any of the keys, preferring the longest
+    /// key when several share a prefix
+    pub(crate) fn pattern(&self) -> String {
+        let mut keys: Vec<_> = self
+            .0
+            .keys()
+            .map(|key| String::from_utf8_lossy(key))
+            .collect();
+        keys.sort_by(|a, b| b.len().cmp(&a.len()).then_with(|| a.cmp(b)));
+        let alternation = keys
+            .iter()
+            .map(|key| regex::escape(key))
+            .collect::<Vec<_>>()
+            .join("|");
+        format!("(?:{alternation})")
+    }
+}
+
+/// Folds the case of `key` the same way the regex engine does with the `i`
+/// flag, if it's text
+///
+/// Every character maps to the smallest one that it matches regardless of
+/// case, so that keys match each other exactly when the regex engine would
+/// match one with the other.
+fn fold_case(key: &[u8]) -> Option<Vec<u8>> {
+    let key = std::str::from_utf8(key).ok()?;
+    Some(key.chars().map(fold_char).collect::<String>().into_bytes())
+}
+
+fn fold_char(c: char) -> char {
+    // Other characters that match ASCII letters, like the Kelvin sign, are
+    // all past ASCII, so the uppercase letter is the smallest of them
+    if c.is_ascii() {
+        return c.to_ascii_uppercase();
+    }
+
+    let mut class = ClassUnicode::new([ClassUnicodeRange::new(c, c)]);
+    class.case_fold_simple();
+    class.ranges().first().map_or(c, ClassUnicodeRange::start)
+}
+
+/// Looks up the text of each match (or its `key` capture) in a
+/// [`ReplaceTable`], leaving unmapped matches untouched
+pub(crate) struct TableReplacer<'t> {
+    pub(crate) table: &'t ReplaceTable,
+    /// The table from [`ReplaceTable::folded`], when matching ignores case
+    pub(crate) folded: Option<&'t ReplaceTable>,
+    pub(crate) key_group: Option<usize>,
+    /// The last key that wasn't in the table
+    pub(crate) missing: Option<Vec<u8>>,
+}
+
+impl TableReplacer<'_> {
+    /// Appends the value for `key` to `dst`, or `key` itself if there's none
+    pub(crate) fn replace_key(&mut self, key: &[u8], dst: &mut Vec<u8>) {
+        let value = self.table.get(key).or_else(|| {
+            let folded = self.folded?;
+            folded.get(&fold_case(key)?)
+        });
+        match value {
+            Some(value) => dst.extend_from_slice(value),
+            None => {
+                dst.extend_from_slice(key);
+                self.missing = Some(key.to_vec());
+            }
+        }
+    }
+}
+
+impl regex::bytes::Replacer for TableReplacer<'_> {
+    fn replace_append(
+        &mut self,
+        caps: &regex::bytes::Captures<'_>,
+        dst: &mut Vec<u8>,
+    ) {
+        // unwrap on 0 is OK because captures only reports matches
+        let whole = caps.get(0).unwrap();
+        let key = self.key_group.and_then(|i| caps.get(i)).unwrap_or(whole);
+        let haystack = whole.as_bytes();
+        let (before, after) = (
+            &haystack[..key.start() - whole.start()],
+            &haystack[key.end() - whole.start()..],
+        );
+
+        dst.extend_from_slice(before);
+        self.replace_key(key.as_bytes(), dst);
+        dst.extend_from_slice(after);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(table: &ReplaceTable) -> Vec<(String, String)> {
+        let mut entries: Vec<_> = table
+            .0
+            .iter()
+            .map(|(k, v)| {
+                (
+                    String::from_utf8(k.clone()).unwrap(),
+                    String::from_utf8(v.clone()).unwrap(),
+                )
+            })
+            .collect();
+        entries.sort();
+        entries
+    }
+
+    fn pairs(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn folded() {
+        let table = ReplaceTable::parse_tsv("Foo\t1\nfOO\t2\nbar\t3").unwrap();
+        assert_eq!(
+            entries(&table.folded()),
+            pairs(&[("BAR", "3"), ("FOO", "1")])
+        );
+        // The same way as the regex engine, which goes by single characters
+        let table = ReplaceTable::parse_tsv("ς\t1\nK\t2").unwrap();
+        assert_eq!(entries(&table.folded()), pairs(&[("K", "2"), ("Σ", "1")]));
+    }
+
+    #[test]
+    fn tsv() {
+        let table = ReplaceTable::parse_tsv("old\tnew\n\na b\tc\td\n").unwrap();
+        assert_eq!(entries(&table), pairs(&[("a b", "c\td"), ("old", "new")]));
+
+        let err = ReplaceTable::parse_tsv("ok\tfine\nbroken\n").unwrap_err();
+        assert_eq!(err, "line 2: missing tab separator");
+    }
+
+    #[test]
+    fn csv() {
+        let table = ReplaceTable::parse_csv(
+            "old,new\r\n\"with,comma\",\"quoted \"\"value\"\"\"\nempty,\n",
+        )
+        .unwrap();
+        assert_eq!(
+            entries(&table),
+            pairs(&[
+                ("empty", ""),
+                ("old", "new"),
+                ("with,comma", "quoted \"value\"")
+            ])
+        );
+
+        let err = ReplaceTable::parse_csv("a,b\na,b,c").unwrap_err();
+        assert_eq!(err, "line 2: expected 2 fields, found 3");
+    }
+
+    #[test]
+    fn json() {
+        let table = ReplaceTable::parse_json(r#"{"old": "new", "foo": "bar"}"#)
+            .unwrap();
+        assert_eq!(entries(&table), pairs(&[("foo", "bar"), ("old", "new")]));
+
+        ReplaceTable::parse_json(r#"{"old": 1}"#).unwrap_err();
+    }
+
+    #[test]
+    fn pattern_prefers_longest_key() {
+        let table = ReplaceTable::parse_tsv("a\t1\nab\t2\na.c\t3").unwrap();
+        assert_eq!(table.pattern(), r"(?:a\.c|ab|a)");
+    }
+}
@@ -167,3 +167,627 @@ fn escaping_unnecessarily() {
     }
     .test();
 }
+
+struct TableReplace {
+    table: &'static str,
+    look_for: Option<&'static str>,
+    flags: Option<&'static str>,
+    src: &'static str,
+    expected: &'static str,
+}
+
+impl TableReplace {
+    fn test(&self) {
+        let replacer = Replacer::with_table(
+            ReplaceTable::parse_tsv(self.table).unwrap(),
+            self.look_for.map(ToOwned::to_owned),
+            self.flags.map(ToOwned::to_owned),
+            0,
+        )
+        .unwrap();
+
+        let binding = replacer.replace(self.src.as_bytes());
+        let actual = std::str::from_utf8(&binding).unwrap();
+
+        assert_eq!(self.expected, actual);
+    }
+}
+
+#[test]
+fn table_replaces_all_keys() {
+    TableReplace {
+        table: "foo\tbar\nold_api\tnew_api\nold\tNEW",
+        look_for: None,
+        flags: None,
+        src: "foo(old_api, old.x)",
+        expected: "bar(new_api, NEW.x)",
+    }
+    .test();
+}
+
+#[test]
+fn table_keys_are_literal() {
+    TableReplace {
+        table: "a.b\tx",
+        look_for: None,
+        flags: None,
+        src: "a.b acb",
+        expected: "x acb",
+    }
+    .test();
+}
+
+#[test]
+fn table_full_word() {
+    TableReplace {
+        table: "abc\tdef",
+        look_for: None,
+        flags: Some("w"),
+        src: "abcd abc",
+        expected: "abcd def",
+    }
+    .test();
+}
+
+#[test]
+fn table_with_pattern_leaves_unmapped_matches() {
+    TableReplace {
+        table: "one\t1\ntwo\t2",
+        look_for: Some(r"\w+"),
+        flags: None,
+        src: "one two three",
+        expected: "1 2 three",
+    }
+    .test();
+}
+
+#[test]
+fn table_with_key_capture() {
+    TableReplace {
+        table: "old\tnew",
+        look_for: Some(r"api\.(?P<key>\w+)\("),
+        flags: None,
+        src: "api.old() old() api.other()",
+        expected: "api.new() old() api.other()",
+    }
+    .test();
+}
+
+#[test]
+fn literal_limit() {
+    let replacer =
+        Replacer::new("ab".into(), "x".into(), true, None, 2).unwrap();
+    assert_eq!(&*replacer.replace(b"ababab"), b"xxab");
+}
+
+#[test]
+fn literal_case_insensitive() {
+    Replace {
+        look_for: "a.c",
+        replace_with: "x",
+        literal: true,
+        flags: Some("i"),
+        src: "A.C abc a.c",
+        expected: "x abc x",
+    }
+    .test();
+}
+
+#[test]
+fn literal_empty_find() {
+    Replace {
+        look_for: "",
+        replace_with: "-",
+        literal: true,
+        src: "ab",
+        expected: "-a-b-",
+        ..Default::default()
+    }
+    .test();
+}
+
+#[test]
+fn template_without_captures() {
+    Replace {
+        look_for: r"\d",
+        replace_with: "$$1 $ $$$",
+        src: "a1",
+        expected: "a$1 $ $$",
+        ..Default::default()
+    }
+    .test();
+}
+
+#[test]
+fn table_case_insensitive_falls_back_to_regex() {
+    TableReplace {
+        table: "foo\tbar",
+        look_for: None,
+        flags: Some("i"),
+        src: "foo FOO Foo",
+        expected: "bar bar bar",
+    }
+    .test();
+}
+
+#[test]
+fn table_case_insensitive_prefers_exact_keys() {
+    TableReplace {
+        table: "FOO\t1\nfoo\t2\nÉtÉ\t3",
+        look_for: Some(r"\w+"),
+        flags: Some("i"),
+        src: "FOO foo fOO été",
+        expected: "1 2 1 3",
+    }
+    .test();
+}
+
+#[test]
+fn table_case_insensitive_non_ascii() {
+    TableReplace {
+        table: "σοφός\tsage\nstraße\tstreet",
+        look_for: None,
+        flags: Some("i"),
+        src: "ΣΟΦΌΣ σοφόσ STRAẞE",
+        expected: "sage sage street",
+    }
+    .test();
+}
+
+#[test]
+fn strict_table() {
+    let table = ReplaceTable::parse_tsv("one\t1\ntwo\t2").unwrap();
+    let replacer = ReplacerBuilder::with_table(table, Some(r"\w+".into()))
+        .strict_map()
+        .build()
+        .unwrap();
+
+    assert_eq!(&*replacer.try_replace(b"one two").unwrap(), b"1 2");
+    let err = replacer.try_replace(b"one three two").unwrap_err();
+    assert!(matches!(&err, crate::Error::UnmappedMatch(key) if key == "three"));
+    assert_eq!(err.code(), "unmapped-match");
+    // Replacing stops at the unmapped match
+    assert_eq!(&*replacer.replace(b"one three two"), b"1 three two");
+}
+
+#[test]
+fn literal_has_no_effect_on_tables() {
+    let table = ReplaceTable::parse_tsv("one\t$1").unwrap();
+    let replacer = ReplacerBuilder::with_table(table, Some(r"\w+".into()))
+        .literal()
+        .build()
+        .unwrap();
+    assert_eq!(&*replacer.replace(b"one two"), b"$1 two");
+}
+
+#[test]
+fn invalid_tables() {
+    let reason =
+        |table: ReplaceTable| match ReplacerBuilder::with_table(table, None)
+            .build()
+        {
+            Err(crate::Error::InvalidMap(None, reason)) => reason,
+            Err(e) => panic!("expected an invalid map, got {e}"),
+            Ok(_) => panic!("expected an invalid map"),
+        };
+
+    assert_eq!(reason(ReplaceTable::default()), "no entries");
+    let mut table = ReplaceTable::default();
+    table.insert("a", "b");
+    table.insert("", "x");
+    assert_eq!(reason(table), "empty key");
+}
+
+proptest! {
+    #[test]
+    fn literal_matches_regex(
+        needle in "[ab.]{1,3}",
+        replace_with in r"[ab$]{0,3}",
+        haystack in "[ab.\n]{0,32}",
+    ) {
+        let replacer = Replacer::new(
+            needle.clone(),
+            replace_with.clone(),
+            true,
+            None,
+            0,
+        )
+        .unwrap();
+        let expected = Regex::new(&regex::escape(&needle))
+            .unwrap()
+            .replace_all(
+                haystack.as_bytes(),
+                regex::bytes::NoExpand(replace_with.as_bytes()),
+            );
+        prop_assert_eq!(replacer.replace(haystack.as_bytes()), expected);
+    }
+
+    #[test]
+    fn table_matches_alternation(
+        keys in prop::collection::vec("[ab]{1,3}", 1..4),
+        haystack in "[ab ]{0,32}",
+    ) {
+        let mut table = ReplaceTable::default();
+        for (i, key) in keys.iter().enumerate() {
+            table.insert(key, &i.to_string());
+        }
+        let literal =
+            Replacer::with_table(table.clone(), None, None, 0).unwrap();
+        let regex = Replacer::with_table(
+            table.clone(),
+            Some(table.pattern()),
+            None,
+            0,
+        )
+        .unwrap();
+        prop_assert_eq!(
+            literal.replace(haystack.as_bytes()),
+            regex.replace(haystack.as_bytes())
+        );
+    }
+}
+
+proptest! {
+    #[test]
+    fn regex_matches_upstream(
+        pattern in prop::sample::select(vec![
+            "", "a*", "a?", "b+", r"\b", r"^", r"$", "(a)|(b)", "a*?", "é?",
+        ]),
+        replace_with in prop::sample::select(vec!["", "x", "<$1$2>"]),
+        haystack in "[abé \n]{0,32}",
+    ) {
+        let replacer = Replacer::new(
+            pattern.into(),
+            replace_with.into(),
+            false,
+            None,
+            0,
+        )
+        .unwrap();
+        let upstream = regex::bytes::RegexBuilder::new(pattern)
+            .multi_line(true)
+            .build()
+            .unwrap();
+        prop_assert_eq!(
+            replacer.replace(haystack.as_bytes()),
+            upstream.replace_all(haystack.as_bytes(), replace_with.as_bytes())
+        );
+    }
+}
+
+#[test]
+fn builder() {
+    let replacer = ReplacerBuilder::new("a.c", "x")
+        .literal()
+        .case_insensitive()
+        .limit(1)
+        .build()
+        .unwrap();
+    assert_eq!(&*replacer.replace(b"abc A.C a.c"), b"abc x a.c");
+
+    let replacer = ReplacerBuilder::new("^a.b$", "x")
+        .dot_all()
+        .multi_line()
+        .build()
+        .unwrap();
+    assert_eq!(&*replacer.replace(b"a\nb\na\nb"), b"x\nx");
+
+    let replacer = ReplacerBuilder::new("^a.b$", "x")
+        .dot_all()
+        .build()
+        .unwrap();
+    assert_eq!(&*replacer.replace(b"a\nb\na\nb"), b"a\nb\na\nb");
+}
+
+#[test]
+fn whole_word_keeps_other_flags() {
+    // `w` used to rebuild the regex and drop any flag given before it
+    Replace {
+        look_for: "foo|bar",
+        replace_with: "x",
+        flags: Some("iw"),
+        src: "FOO foobar Bar",
+        expected: "x foobar x",
+        ..Default::default()
+    }
+    .test();
+}
+
+#[test]
+fn invalid_flags() {
+    let parse = |flags: &str| flags.parse::<Flags>().unwrap_err();
+    assert!(matches!(parse("iq"), crate::Error::UnknownFlag('q')));
+    assert!(matches!(
+        parse("ic"),
+        crate::Error::ConflictingFlags('c', 'i')
+    ));
+    assert!(matches!(
+        parse("ci"),
+        crate::Error::ConflictingFlags('c', 'i')
+    ));
+    assert!(matches!(
+        parse("mse"),
+        crate::Error::ConflictingFlags('e', 'm')
+    ));
+
+    let err = ReplacerBuilder::new("a", "b")
+        .single_line()
+        .multi_line()
+        .build()
+        .err()
+        .unwrap();
+    assert!(matches!(err, crate::Error::ConflictingFlags('e', 'm')));
+}
+
+#[test]
+fn invalid_regex_span() {
+    let error = |look_for: &str, flags: &str| {
+        ReplacerBuilder::new(look_for, "")
+            .flags(flags.parse().unwrap())
+            .build()
+            .err()
+            .unwrap()
+    };
+
+    let err = error("ab{2,1}", "");
+    assert_eq!(err.code(), "invalid-regex");
+    assert_eq!(err.argument(), Some(crate::Argument::Find));
+    assert_eq!(err.span(), Some(2..7));
+    assert_eq!(
+        err.to_string(),
+        "invalid regex: invalid repetition count range, the start must be \
+         <= the end\nab{2,1}\n  ^^^^^"
+    );
+    // The wrapping that whole words get doesn't move the span
+    assert_eq!(error("é)", "w").span(), Some(2..3));
+    assert_eq!(error("(?<x>a)(?<x>b)", "i").span(), Some(10..11));
+
+    let json = error("a{2,1}", "").to_json();
+    assert_eq!(json["code"], "invalid-regex");
+    assert_eq!(json["argument"], "find");
+    assert_eq!(json["value"], "a{2,1}");
+    assert_eq!(json["span"]["start"], 1);
+    assert_eq!(json["span"]["end"], 6);
+}
+
+#[test]
+fn invalid_regex_hints() {
+    let error =
+        |look_for: &str| match ReplacerBuilder::new(look_for, "").build() {
+            Err(crate::Error::Regex(e)) => e,
+            _ => panic!("{look_for} should be invalid"),
+        };
+    let hint = |look_for: &str| error(look_for).hint().map(str::to_owned);
+
+    assert_eq!(error("f(x").meant_literally(), Some('('));
+    assert_eq!(error("(?<=a)b").meant_literally(), None);
+
+    assert!(hint("f(x").unwrap().contains("literal `(`"));
+    assert!(hint("f(x))").unwrap().contains("literal `)`"));
+    assert!(hint("+1").unwrap().contains("literal `+`"));
+    // The library has no `-F` to point to
+    assert!(!hint("f(x").unwrap().contains("-F"));
+    assert!(hint("(?<=a)b").unwrap().contains("before the match"));
+    assert!(hint("a(?=b)").unwrap().contains("after the match"));
+    assert!(hint("a(?<!b)").unwrap().contains("negated class"));
+    assert!(hint(r"(a)\1").unwrap().contains("`$1`"));
+    assert_eq!(hint("a{2,1}"), None);
+}
+
+#[test]
+fn warnings() {
+    let codes = |builder: ReplacerBuilder| -> Vec<&str> {
+        let replacer = builder.build().unwrap();
+        replacer.warnings().iter().map(Warning::code).collect()
+    };
+
+    assert!(
+        codes(ReplacerBuilder::new("(a)(?<x>b)", "$0$1$2$x${x}")).is_empty()
+    );
+    assert_eq!(
+        codes(ReplacerBuilder::new("(a)(b)", "$3${y}$$4")),
+        ["missing-group", "missing-name"]
+    );
+    assert_eq!(
+        codes(ReplacerBuilder::new(r"a\nb|c", "")),
+        ["newline-in-line-mode"]
+    );
+    assert_eq!(codes(ReplacerBuilder::new("a\n?", "").literal()), [""; 0]);
+    assert_eq!(
+        codes(ReplacerBuilder::new("a(\n)?", "")),
+        ["newline-in-line-mode"]
+    );
+    assert_eq!(
+        codes(ReplacerBuilder::new(r"a|\n", "")),
+        ["newline-in-line-mode"]
+    );
+    assert_eq!(
+        codes(ReplacerBuilder::new(r"(?-u:[\n,])", "")),
+        ["newline-in-line-mode"]
+    );
+    assert!(codes(ReplacerBuilder::new(r"[^a]\s", "")).is_empty());
+    assert!(codes(ReplacerBuilder::new(r"\s", "")).is_empty());
+    assert_eq!(
+        codes(ReplacerBuilder::new("^a$", "").single_line()),
+        ["single-line-anchor"]
+    );
+    assert!(codes(ReplacerBuilder::new("^a$", "")).is_empty());
+    assert_eq!(
+        codes(ReplacerBuilder::new(r"\Aa", "").single_line()),
+        ["single-line-anchor"]
+    );
+
+    let replacer = ReplacerBuilder::new("(a)", "x$2").build().unwrap();
+    assert_eq!(
+        replacer.warnings(),
+        [Warning::MissingGroup {
+            reference: "$2".into(),
+            groups: 1,
+            span: 1..3,
+        }]
+    );
+}
+
+#[cfg(feature = "io")]
+#[test]
+fn warnings_apply_to_some_modes() {
+    use crate::{ProcessOptions, RecordSeparator};
+
+    let line = ProcessOptions::default();
+    let across = ProcessOptions {
+        across: true,
+        ..Default::default()
+    };
+    let null_data = ProcessOptions {
+        record_separator: RecordSeparator::Bytes(vec![b'\0']),
+        ..Default::default()
+    };
+
+    assert!(Warning::Newline.applies_to(&line));
+    assert!(!Warning::Newline.applies_to(&across));
+    assert!(!Warning::Newline.applies_to(&null_data));
+    assert!(!Warning::SingleLineAnchor.applies_to(&line));
+    assert!(Warning::SingleLineAnchor.applies_to(&across));
+}
+
+#[cfg(feature = "io")]
+#[test]
+fn needs_newline() {
+    use crate::ProcessOptions;
+
+    let line = ProcessOptions::default();
+    let across = ProcessOptions {
+        across: true,
+        ..Default::default()
+    };
+    let check = |builder: ReplacerBuilder| {
+        let replacer = builder.build().unwrap();
+        assert!(replacer.check_mode(&across).is_ok());
+        match replacer.check_mode(&line) {
+            Err(crate::Error::NeedsNewline) => {
+                // Failing makes the warning redundant
+                assert!(replacer.warnings().is_empty());
+                true
+            }
+            result => {
+                result.unwrap();
+                false
+            }
+        }
+    };
+
+    assert!(check(ReplacerBuilder::new(r"\n", "")));
+    assert!(check(ReplacerBuilder::new(r"a\r?\n+b", "")));
+    assert!(check(ReplacerBuilder::new(r"(?:a\n|\nb)", "")));
+    assert!(check(ReplacerBuilder::new(r"(?-u:[\n])", "")));
+    assert!(check(ReplacerBuilder::new("\n", "").literal()));
+    assert!(check(ReplacerBuilder::new("a\n", "").whole_word()));
+    assert!(!check(ReplacerBuilder::new(r"a\n?", "")));
+    assert!(!check(ReplacerBuilder::new(r"a|\n", "")));
+    assert!(!check(ReplacerBuilder::new(r"\s", "")));
+    assert!(!check(ReplacerBuilder::new(r"[^a]", "")));
+
+    let table = |csv: &str| {
+        ReplacerBuilder::with_table(ReplaceTable::parse_csv(csv).unwrap(), None)
+    };
+    assert!(check(table("\"a\nb\",c")));
+    assert!(!check(table("\"a\nb\",c\nd,e")));
+}
+
+fn flags_and_permutation() -> impl Strategy<Value = (String, String)> {
+    prop::sample::subsequence(vec!['c', 'i', 'm', 'e', 's', 'w', 'w'], 0..=7)
+        .prop_flat_map(|flags| {
+            (Just(flags.clone()), Just(flags).prop_shuffle())
+        })
+        .prop_map(|(flags, permuted)| {
+            (flags.into_iter().collect(), permuted.into_iter().collect())
+        })
+}
+
+proptest! {
+    #[test]
+    fn flag_order_doesnt_matter(
+        (flags, permuted) in flags_and_permutation(),
+        haystack in "[aA \n]{0,16}",
+    ) {
+        let build = |flags: &str| {
+            Replacer::new(
+                r"^a.$|\ba".into(),
+                "x".into(),
+                false,
+                Some(flags.into()),
+                0,
+            )
+        };
+        match (build(&flags), build(&permuted)) {
+            (Ok(a), Ok(b)) => prop_assert_eq!(
+                a.replace(haystack.as_bytes()),
+                b.replace(haystack.as_bytes())
+            ),
+            (Err(a), Err(b)) => prop_assert_eq!(a.to_string(), b.to_string()),
+            _ => prop_assert!(false, "{flags:?} and {permuted:?} differ"),
+        }
+    }
+
+    #[test]
+    fn unknown_flags_are_rejected(
+        flags in "[cimesw]{0,3}[^cimesw][cimesw]{0,3}",
+    ) {
+        let unknown = flags.chars().find(|c| !"cimesw".contains(*c)).unwrap();
+        prop_assert!(matches!(
+            flags.parse::<Flags>(),
+            Err(crate::Error::UnknownFlag(c)) if c == unknown
+        ));
+    }
+}
+
+#[test]
+fn visitor_decides() {
+    let replacer = ReplacerBuilder::new(r"(\w)(\d)", "$2$1")
+        .visitor(|m: &Match<'_>| match m.captures.unwrap()[1][0] {
+            b'a' => Decision::Veto,
+            b'b' => Decision::Replace(b"_".to_vec()),
+            _ => Decision::Accept,
+        })
+        .limit(2)
+        .build()
+        .unwrap();
+    // Vetoed matches don't count towards the limit
+    assert_eq!(&*replacer.replace(b"a1 b2 c3 d4"), b"a1 _ 3c d4");
+
+    let replacer = ReplacerBuilder::new("x", "y")
+        .literal()
+        .visitor(|_: &Match<'_>| Decision::Veto)
+        .build()
+        .unwrap();
+    assert!(matches!(replacer.replace(b"xx"), Cow::Borrowed(_)));
+}
+
+#[test]
+fn visitor_sees_matches() {
+    let seen = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let replacer = ReplacerBuilder::new("b+", "<$0>")
+        .visitor({
+            let seen = seen.clone();
+            move |m: &Match<'_>| {
+                seen.lock().unwrap().push((
+                    m.span.clone(),
+                    m.line,
+                    m.text.to_vec(),
+                    m.replacement.to_vec(),
+                ));
+                Decision::Accept
+            }
+        })
+        .build()
+        .unwrap();
+    replacer.replace(b"ab\n\nbb\n");
+
+    assert_eq!(
+        *seen.lock().unwrap(),
+        [
+            (1..2, 1, b"b".to_vec(), b"<b>".to_vec()),
+            (4..6, 3, b"bb".to_vec(), b"<bb>".to_vec()),
+        ]
+    );
+}
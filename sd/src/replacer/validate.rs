@@ -1,4 +1,4 @@
-use std::{error::Error, fmt, str::CharIndices};
+use std::{error::Error, fmt, ops::Range, str::CharIndices};
 
 #[derive(Debug)]
 pub struct InvalidReplaceCapture {
@@ -9,93 +9,22 @@ pub struct InvalidReplaceCapture {
 
 impl Error for InvalidReplaceCapture {}
 
-// NOTE: This code is much more allocation heavy than it needs to be, but it's
-//       only displayed as a hard error to the user, so it's not a big deal
-impl fmt::Display for InvalidReplaceCapture {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        #[derive(Clone, Copy)]
-        enum SpecialChar {
-            Newline,
-            CarriageReturn,
-            Tab,
-        }
-
-        impl SpecialChar {
-            fn new(c: char) -> Option<Self> {
-                match c {
-                    '\n' => Some(Self::Newline),
-                    '\r' => Some(Self::CarriageReturn),
-                    '\t' => Some(Self::Tab),
-                    _ => None,
-                }
-            }
-
-            /// Renders as the character from the "Control Pictures" block
-            ///
-            /// https://en.wikipedia.org/wiki/Control_Pictures
-            fn render(self) -> char {
-                match self {
-                    Self::Newline => '␊',
-                    Self::CarriageReturn => '␍',
-                    Self::Tab => '␉',
-                }
-            }
-        }
-
-        let Self {
-            original_replace,
-            invalid_ident,
-            num_leading_digits,
-        } = self;
-
-        // Build up the error to show the user
-        let mut formatted = String::new();
-        let mut arrows_start = Span::start_at(0);
-        for (byte_index, c) in original_replace.char_indices() {
-            let (prefix, suffix, text) = match SpecialChar::new(c) {
-                Some(c) => {
-                    (
-                        Some("" /* special prefix */),
-                        Some("" /* special suffix */),
-                        c.render(),
-                    )
-                }
-                None => {
-                    let (prefix, suffix) = if byte_index == invalid_ident.start
-                    {
-                        (Some("" /* error prefix */), None)
-                    } else if byte_index
-                        == invalid_ident.end.checked_sub(1).unwrap()
-                    {
-                        (None, Some("" /* error suffix */))
-                    } else {
-                        (None, None)
-                    };
-                    (prefix, suffix, c)
-                }
-            };
-
-            if let Some(prefix) = prefix {
-                formatted.push_str(prefix);
-            }
-            formatted.push(text);
-            if let Some(suffix) = suffix {
-                formatted.push_str(suffix);
-            }
+impl InvalidReplaceCapture {
+    /// The replacement text with the ambiguous capture group in it
+    pub fn replace_with(&self) -> &str {
+        &self.original_replace
+    }
 
-            if byte_index < invalid_ident.start {
-                // Assumes that characters have a base display width of 1. While
-                // that's not technically true, it's near impossible to do right
-                // since the specifics on text rendering is up to the user's
-                // terminal/font. This _does_ rely on variable-width characters
-                // like \n, \r, and \t getting converting to single character
-                // representations above
-                arrows_start.start += 1;
-            }
-        }
+    /// Byte offsets of the ambiguous capture group in [`Self::replace_with`]
+    pub fn span(&self) -> Range<usize> {
+        self.invalid_ident.start..self.invalid_ident.end
+    }
+}
 
-        let ident = invalid_ident.slice(original_replace);
-        let (number, the_rest) = ident.split_at(*num_leading_digits);
+impl fmt::Display for InvalidReplaceCapture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ident = self.invalid_ident.slice(&self.original_replace);
+        let (number, the_rest) = ident.split_at(self.num_leading_digits);
 
         writeln!(
             f,
@@ -108,14 +37,40 @@ impl fmt::Display for InvalidReplaceCapture {
             "hint: Use curly braces to disambiguate it `{disambiguous}`."
         )?;
 
-        writeln!(f, "{}", formatted)?;
+        write!(f, "{}", underline(&self.original_replace, self.span()))
+    }
+}
 
-        // This relies on all non-curly-braced capture chars being 1 byte
-        let arrows_span = arrows_start.end_offset(invalid_ident.len());
-        let mut arrows = " ".repeat(arrows_span.start);
-        arrows.push_str(&"^".repeat(arrows_span.len()));
-        write!(f, "{}", arrows)
+/// Renders `text` on one line, and carets under the chars in `span` on the
+/// next
+// NOTE: This code is much more allocation heavy than it needs to be, but it's
+//       only displayed as a hard error to the user, so it's not a big deal
+pub(crate) fn underline(text: &str, span: Range<usize>) -> String {
+    /// Renders control characters as the character from the "Control
+    /// Pictures" block
+    ///
+    /// https://en.wikipedia.org/wiki/Control_Pictures
+    fn render(c: char) -> char {
+        match c {
+            '\n' => '␊',
+            '\r' => '␍',
+            '\t' => '␉',
+            c => c,
+        }
     }
+
+    let mut formatted: String = text.chars().map(render).collect();
+    // Assumes that characters have a base display width of 1. While that's
+    // not technically true, it's near impossible to do right since the
+    // specifics on text rendering is up to the user's terminal/font. This
+    // _does_ rely on variable-width characters like \n, \r, and \t getting
+    // converted to single character representations above
+    let before = text[..span.start].chars().count();
+    let width = text[span].chars().count().max(1);
+    formatted.push('\n');
+    formatted.push_str(&" ".repeat(before));
+    formatted.push_str(&"^".repeat(width));
+    formatted
 }
 
 pub fn validate_replace(s: &str) -> Result<(), InvalidReplaceCapture> {
@@ -138,6 +93,26 @@ pub fn validate_replace(s: &str) -> Result<(), InvalidReplaceCapture> {
     Ok(())
 }
 
+/// Whether the replacement text refers to any capture groups at all
+pub(crate) fn has_captures(s: &str) -> bool {
+    ReplaceCaptureIter::new(s).next().is_some()
+}
+
+/// The capture groups that the replacement text refers to, by their index or
+/// name, along with where each reference (including its `$`) is
+pub(crate) fn capture_refs(
+    s: &str,
+) -> impl Iterator<Item = (&str, Range<usize>)> {
+    ReplaceCaptureIter::new(s).map(|cap| {
+        let name = cap
+            .name
+            .strip_prefix('{')
+            .and_then(|name| name.strip_suffix('}'))
+            .unwrap_or(cap.name);
+        (name, cap.span.start - 1..cap.span.end)
+    })
+}
+
 #[derive(Clone, Copy, Debug)]
 struct Span {
     start: usize,
@@ -158,10 +133,6 @@ impl Span {
     fn slice(self, s: &str) -> &str {
         &s[self.start..self.end]
     }
-
-    fn len(self) -> usize {
-        self.end - self.start
-    }
 }
 
 #[derive(Clone, Copy)]
@@ -0,0 +1,61 @@
+use std::{ops::Range, path::Path};
+
+use regex::bytes::Captures;
+
+/// A match that is about to be replaced, as seen by a [`Visitor`]
+///
+/// In inputs that get decoded from another encoding, offsets and text are
+/// those of the decoded UTF-8.
+pub struct Match<'a> {
+    /// The file the match is in, if it came from one
+    pub path: Option<&'a Path>,
+    /// Byte offsets of the match from the start of the input
+    pub span: Range<usize>,
+    /// The line the match starts on, counting from 1
+    pub line: usize,
+    /// The text that matched
+    pub text: &'a [u8],
+    /// The capture groups of the match, which only regex patterns have
+    pub captures: Option<&'a Captures<'a>>,
+    /// What the match would be replaced with
+    pub replacement: &'a [u8],
+}
+
+/// What to do with a match, as decided by a [`Visitor`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decision {
+    /// Replace it as proposed
+    Accept,
+    /// Leave it as it is, without counting towards the replacement limit
+    Veto,
+    /// Replace it with something else
+    Replace(Vec<u8>),
+}
+
+/// Looks at every match before it gets replaced, and decides what happens to
+/// it
+///
+/// Inputs may be processed on several threads at once, so a visitor has to
+/// be shareable between them. Any closure taking a [`Match`] is a visitor.
+///
+/// ```
+/// use sd::{Decision, ReplacerBuilder};
+///
+/// let replacer = ReplacerBuilder::new(r"\d+", "N")
+///     .visitor(|m: &sd::Match<'_>| match m.text {
+///         b"0" => Decision::Veto,
+///         _ => Decision::Accept,
+///     })
+///     .build()?;
+/// assert_eq!(&*replacer.replace(b"1 0 23"), b"N 0 N");
+/// # Ok::<(), sd::Error>(())
+/// ```
+pub trait Visitor: Send + Sync {
+    fn visit(&self, m: &Match<'_>) -> Decision;
+}
+
+impl<F: Fn(&Match<'_>) -> Decision + Send + Sync> Visitor for F {
+    fn visit(&self, m: &Match<'_>) -> Decision {
+        self(m)
+    }
+}
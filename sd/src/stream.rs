@@ -0,0 +1,461 @@
+use std::{
+    io::{BufRead, Read, Write},
+    path::Path,
+};
+
+use crate::{
+    LineEnding, ProcessOptions, RecordSeparator, Replacer, Result,
+    encoding::EncodeWriter, replacer::ReplaceState,
+};
+
+/// Replaces the contents of `reader` without loading it all into memory,
+/// either one record at a time or through a sliding window
+///
+/// Inputs in another encoding are decoded to UTF-8 on the way in and encoded
+/// back on the way out. `path` is only passed on to the replacer's visitor.
+pub(crate) fn process_reader(
+    replacer: &Replacer,
+    mut reader: Box<dyn BufRead + '_>,
+    path: Option<&Path>,
+    options: &ProcessOptions,
+    writer: &mut dyn Write,
+) -> Result<()> {
+    let Some(transcoding) = options.encoding.detect(reader.fill_buf()?) else {
+        return process_decoded(replacer, reader, path, options, writer);
+    };
+
+    let reader = transcoding.decode_reader(reader)?;
+    let mut writer = EncodeWriter::new(writer, transcoding)?;
+    process_decoded(replacer, reader, path, options, &mut writer)?;
+    writer.finish()?;
+
+    Ok(())
+}
+
+fn process_decoded(
+    replacer: &Replacer,
+    reader: Box<dyn BufRead + '_>,
+    path: Option<&Path>,
+    options: &ProcessOptions,
+    writer: &mut dyn Write,
+) -> Result<()> {
+    match options.window {
+        Some(window) => {
+            process_reader_windowed(replacer, reader, path, window, writer)
+        }
+        None => process_reader_line_by_line(
+            replacer,
+            reader,
+            path,
+            &options.record_separator,
+            options.eol,
+            writer,
+        ),
+    }
+}
+
+/// Replaces one record at a time, where records are lines unless another
+/// separator is given
+///
+/// Lines ending in `\r\n` are matched without the `\r`, and end up with the
+/// line ending chosen by `eol`.
+pub(crate) fn process_reader_line_by_line(
+    replacer: &Replacer,
+    mut reader: Box<dyn BufRead + '_>,
+    path: Option<&Path>,
+    separator: &RecordSeparator,
+    eol: LineEnding,
+    writer: &mut dyn Write,
+) -> Result<()> {
+    let mut records = Records::new(replacer, path, separator, eol);
+    let mut chunk = vec![0u8; CHUNK_SIZE];
+    loop {
+        let n = reader.read(&mut chunk)?;
+        records.feed(&chunk[..n], n == 0, writer)?;
+        if n == 0 {
+            return Ok(());
+        }
+    }
+}
+
+/// How much is read at a time when going one record at a time
+pub(crate) const CHUNK_SIZE: usize = 8192;
+
+/// Replaces one record at a time in input that is fed to it in chunks of any
+/// size, for readers that can't be pulled from like [`BufRead`]
+pub(crate) struct Records<'a> {
+    replacer: &'a Replacer,
+    path: Option<&'a Path>,
+    separator: &'a RecordSeparator,
+    eol: LineEnding,
+    is_lines: bool,
+    /// The start of a record that hasn't ended yet
+    pending: Vec<u8>,
+    /// No separator starts in `pending` before this offset
+    searched: usize,
+    /// Where `pending` starts in the whole input
+    offset: usize,
+    /// How many newlines come before `pending`, which are only counted for a
+    /// visitor
+    newlines: usize,
+}
+
+impl<'a> Records<'a> {
+    pub(crate) fn new(
+        replacer: &'a Replacer,
+        path: Option<&'a Path>,
+        separator: &'a RecordSeparator,
+        eol: LineEnding,
+    ) -> Self {
+        Self {
+            replacer,
+            path,
+            separator,
+            eol,
+            is_lines: separator.is_lines(),
+            pending: Vec::with_capacity(256),
+            searched: 0,
+            offset: 0,
+            newlines: 0,
+        }
+    }
+
+    /// Writes out every record that `chunk` completes, and the last one
+    /// once `eof` is reached
+    pub(crate) fn feed(
+        &mut self,
+        chunk: &[u8],
+        eof: bool,
+        writer: &mut dyn Write,
+    ) -> Result<()> {
+        let pending = &mut self.pending;
+        pending.extend_from_slice(chunk);
+
+        let mut start = 0;
+        while let Some((end, next)) =
+            self.separator.find(pending, self.searched, eof)
+        {
+            // Found a complete record
+            let (record, separator) = if self.is_lines {
+                self.eol.split(&pending[start..end])
+            } else {
+                (&pending[start..end], &pending[end..next])
+            };
+            let mut state =
+                ReplaceState::at(self.path, self.offset + start, self.newlines);
+            let replaced = self.replacer.replace_in(record, &mut state);
+            state.check()?;
+            writer.write_all(&replaced)?;
+            writer.write_all(separator)?;
+            if self.replacer.has_visitor() {
+                self.newlines +=
+                    memchr::memchr_iter(b'\n', &pending[start..next]).count();
+            }
+            start = next;
+            self.searched = next;
+        }
+
+        if eof {
+            // Finish any remaining record
+            if start < pending.len() {
+                let mut state = ReplaceState::at(
+                    self.path,
+                    self.offset + start,
+                    self.newlines,
+                );
+                let (record, ending) = if self.is_lines {
+                    self.eol.split_last(&pending[start..])
+                } else {
+                    (&pending[start..], &b""[..])
+                };
+                let replaced = self.replacer.replace_in(record, &mut state);
+                state.check()?;
+                writer.write_all(&replaced)?;
+                writer.write_all(ending)?;
+            }
+            pending.clear();
+            return Ok(());
+        }
+
+        // Keep partial record for next chunk
+        pending.drain(..start);
+        self.offset += start;
+        self.searched = self.separator.resume_at(pending);
+
+        Ok(())
+    }
+}
+
+/// Replaces matches that may span lines while only buffering around
+/// `2 * window` bytes at a time
+///
+/// Matches up to `window` bytes long are found exactly like they would be in
+/// the whole input. Longer matches may be cut short or missed entirely.
+pub(crate) fn process_reader_windowed(
+    replacer: &Replacer,
+    mut reader: Box<dyn BufRead + '_>,
+    path: Option<&Path>,
+    window: usize,
+    writer: &mut dyn Write,
+) -> Result<()> {
+    // Already written bytes that are kept in front of the search position,
+    // so that assertions like `^` and `\b` see the same surroundings as they
+    // would in the whole input. One UTF-8 encoded char is all they look at.
+    const CONTEXT: usize = 4;
+
+    let window = window.max(1);
+    let mut buf = Vec::with_capacity(2 * window + CONTEXT);
+    let mut replaced = Vec::new();
+    let mut state = ReplaceState::at(path, 0, 0);
+    // Everything in `buf` before `pos` has already been written
+    let mut pos = 0;
+
+    loop {
+        let mut eof = false;
+        while buf.len() - pos < 2 * window {
+            let chunk = reader.fill_buf()?;
+            if chunk.is_empty() {
+                eof = true;
+                break;
+            }
+            let n = chunk.len();
+            buf.extend_from_slice(chunk);
+            reader.consume(n);
+        }
+
+        // Only matches that start at least `window` bytes before the end of
+        // the buffer are known to be complete
+        let stop = if eof {
+            buf.len()
+        } else {
+            buf.len() - window - 1
+        };
+        replaced.clear();
+        let end =
+            replacer.replace_range(&buf, pos, stop, &mut state, &mut replaced);
+        state.check()?;
+        writer.write_all(&replaced)?;
+
+        if eof {
+            writer.write_all(&buf[end..])?;
+            break;
+        }
+
+        let next = end.max(stop + 1);
+        writer.write_all(&buf[end..next])?;
+        let drained = next.saturating_sub(CONTEXT);
+        state.shift(&buf[..drained]);
+        buf.drain(..drained);
+        pos = next - drained;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::BufReader;
+
+    use super::*;
+
+    use proptest::prelude::*;
+
+    fn windowed(
+        replacer: &Replacer,
+        input: &str,
+        window: usize,
+        read_size: usize,
+    ) -> String {
+        let reader = BufReader::with_capacity(read_size, input.as_bytes());
+        let mut output = Vec::new();
+        process_reader_windowed(
+            replacer,
+            Box::new(reader),
+            None,
+            window,
+            &mut output,
+        )
+        .unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    /// Hands out a single byte per read to exercise records that are split
+    /// across reads
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = self.0.len().min(buf.len()).min(1);
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    fn by_record(
+        replacer: &Replacer,
+        input: &str,
+        separator: &RecordSeparator,
+    ) -> String {
+        by_line(replacer, input, separator, LineEnding::Preserve)
+    }
+
+    fn by_line(
+        replacer: &Replacer,
+        input: &str,
+        separator: &RecordSeparator,
+        eol: LineEnding,
+    ) -> String {
+        let reader = BufReader::new(Trickle(input.as_bytes()));
+        let mut output = Vec::new();
+        process_reader_line_by_line(
+            replacer,
+            Box::new(reader),
+            None,
+            separator,
+            eol,
+            &mut output,
+        )
+        .unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn crlf_lines() {
+        let replacer =
+            Replacer::new(r"\s+$".into(), "".into(), false, None, 0).unwrap();
+        let lines = RecordSeparator::default();
+        let input = "a \r\nb\t\nc \r";
+
+        assert_eq!(
+            by_line(&replacer, input, &lines, LineEnding::Preserve),
+            "a\r\nb\nc\r"
+        );
+        assert_eq!(
+            by_line(&replacer, input, &lines, LineEnding::Lf),
+            "a\nb\nc"
+        );
+        assert_eq!(
+            by_line(&replacer, input, &lines, LineEnding::Crlf),
+            "a\r\nb\r\nc\r"
+        );
+
+        // A last line without a `\n` is split the same way
+        let anchored =
+            Replacer::new("b$".into(), "c".into(), false, None, 0).unwrap();
+        assert_eq!(
+            by_line(&anchored, "a\r\nb\r", &lines, LineEnding::Preserve),
+            "a\r\nc\r"
+        );
+
+        // Other separators are left alone
+        let nul = RecordSeparator::Bytes(b"\0".to_vec());
+        assert_eq!(by_line(&replacer, "a \r\0", &nul, LineEnding::Lf), "a\0");
+    }
+
+    #[test]
+    fn nul_separated_records() {
+        let replacer =
+            Replacer::new("^a".into(), "b".into(), false, None, 0).unwrap();
+        let separator = RecordSeparator::Bytes(b"\0".to_vec());
+        assert_eq!(
+            by_record(&replacer, "a1\na2\0a3\0", &separator),
+            "b1\nb2\0b3\0"
+        );
+    }
+
+    #[test]
+    fn paragraph_records() {
+        let replacer =
+            Replacer::new(r"\n".into(), " ".into(), false, None, 0).unwrap();
+        assert_eq!(
+            by_record(
+                &replacer,
+                "one\ntwo\n\n\nthree\nfour\n\n",
+                &RecordSeparator::Paragraph
+            ),
+            "one two\n\n\nthree four\n\n"
+        );
+    }
+
+    #[test]
+    fn matches_across_lines() {
+        let replacer = Replacer::new(
+            r"\}\n\s*else".into(),
+            "} else".into(),
+            false,
+            None,
+            0,
+        )
+        .unwrap();
+        let input = "if a {\n}\n    else {\n}\n".repeat(50);
+        let expected = "if a {\n} else {\n}\n".repeat(50);
+        assert_eq!(windowed(&replacer, &input, 16, 7), expected);
+    }
+
+    #[test]
+    fn respects_limit() {
+        let replacer =
+            Replacer::new("a\nb".into(), "-".into(), false, None, 2).unwrap();
+        assert_eq!(windowed(&replacer, &"a\nb".repeat(4), 3, 2), "--a\nba\nb");
+    }
+
+    proptest! {
+        #[test]
+        fn records_same_as_split(
+            separator in prop::sample::select(vec!["\n", "ab", "aba", ""]),
+            input in "[ab\n]{0,32}",
+        ) {
+            let replacer =
+                Replacer::new("^a|b$".into(), "-".into(), false, None, 0)
+                    .unwrap();
+            let replace = |record: &str| {
+                String::from_utf8(replacer.replace(record.as_bytes()).into())
+                    .unwrap()
+            };
+            let expected = if separator.is_empty() {
+                let paragraph = regex::Regex::new("\n\n+").unwrap();
+                let mut expected = String::new();
+                let mut last = 0;
+                for m in paragraph.find_iter(&input) {
+                    expected.push_str(&replace(&input[last..m.start()]));
+                    expected.push_str(m.as_str());
+                    last = m.end();
+                }
+                expected + &replace(&input[last..])
+            } else {
+                input.split(separator).map(replace).collect::<Vec<_>>().join(separator)
+            };
+
+            let separator = separator.parse().unwrap();
+            prop_assert_eq!(by_record(&replacer, &input, &separator), expected);
+        }
+
+        #[test]
+        fn same_as_whole_input(
+            pattern in prop::sample::select(vec![
+                r"a\nb", r"^a", r"b$", r"\ba", r"a\b", "a?", r"\n\n",
+                "[ab]{0,2}", r"(?-m)^a", r"(?-m)b$", "(a)(b)?",
+            ]),
+            replace_with in prop::sample::select(vec!["", "x", "<$1>"]),
+            input in "[ab \n]{0,64}",
+            window in 3usize..8,
+            read_size in 1usize..16,
+        ) {
+            let replacer = Replacer::new(
+                pattern.into(),
+                replace_with.into(),
+                false,
+                None,
+                0,
+            )
+            .unwrap();
+            let expected = replacer.replace(input.as_bytes());
+            let expected = std::str::from_utf8(&expected).unwrap();
+            prop_assert_eq!(
+                windowed(&replacer, &input, window, read_size),
+                expected
+            );
+        }
+    }
+}